@@ -0,0 +1,62 @@
+// Raw decode + execute throughput, with no rendering or pacing.
+// Run with `cargo bench`; reports instructions per second.
+
+use chip_8::emulator::Config;
+use chip_8::Emulator;
+use std::hint::black_box;
+use std::time::Instant;
+
+const CYCLES: u32 = 10_000_000;
+const RUNS: u32 = 5;
+
+// an endless loop of ALU instructions
+const ROM: [u8; 26] = [
+    0x60, 0x13, // V0 = 0x13
+    0x61, 0x07, // V1 = 0x07
+    0x80, 0x14, // V0 += V1
+    0x80, 0x15, // V0 -= V1
+    0x80, 0x17, // V0 = V1 - V0
+    0x80, 0x16, // V0 >>= 1
+    0x80, 0x1E, // V0 <<= 1
+    0x80, 0x11, // V0 |= V1
+    0x80, 0x12, // V0 &= V1
+    0x80, 0x13, // V0 ^= V1
+    0x70, 0x01, // V0 += 1
+    0x71, 0xFF, // V1 += 0xFF
+    0x12, 0x00, // jump to 0x200
+];
+
+fn bench(name: &str, decode_cache: bool) {
+    let mut best = f64::MAX;
+
+    for run in 1..=RUNS {
+        let config = Config {
+            decode_cache,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(ROM.to_vec(), config).unwrap();
+
+        let start = Instant::now();
+        for _ in 0..CYCLES {
+            black_box(&mut emulator).perform_fde_cycle().unwrap();
+        }
+        let seconds = start.elapsed().as_secs_f64();
+
+        best = best.min(seconds);
+        println!(
+            "{} run {}: {} instructions in {:.3}s ({:.0} IPS)",
+            name,
+            run,
+            CYCLES,
+            seconds,
+            CYCLES as f64 / seconds
+        );
+    }
+
+    println!("{} best: {:.0} IPS", name, CYCLES as f64 / best);
+}
+
+fn main() {
+    bench("parse", false);
+    bench("decode cache", true);
+}
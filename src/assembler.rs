@@ -0,0 +1,290 @@
+// assembles the mnemonics the decoder displays back into a program.
+// one instruction per line, `;` starts a comment and `NAME:` defines a label for jumps, calls and LD I.
+// DB and DW emit raw bytes and words, for sprites and the address following LD I, LONG
+use crate::decoder::Instruction;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+enum Operand<'a> {
+    Register(u16),
+    Index,
+    IndirectIndex,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    Decimal,
+    Long(Option<&'a str>),
+    Value(&'a str),
+}
+
+fn parse_operand(operand: &str) -> Operand<'_> {
+    let upper = operand.to_ascii_uppercase();
+    match upper.as_str() {
+        "I" => return Operand::Index,
+        "[I]" => return Operand::IndirectIndex,
+        "DT" => return Operand::DelayTimer,
+        "ST" => return Operand::SoundTimer,
+        "K" => return Operand::Key,
+        "F" => return Operand::Font,
+        "B" => return Operand::Decimal,
+        "LONG" => return Operand::Long(None),
+        _ => {}
+    }
+
+    if let Some(register) = upper
+        .strip_prefix('V')
+        .filter(|digit| digit.len() == 1)
+        .and_then(|digit| u16::from_str_radix(digit, 16).ok())
+    {
+        return Operand::Register(register);
+    }
+    if upper.starts_with("LONG ") {
+        return Operand::Long(Some(operand[5..].trim()));
+    }
+    Operand::Value(operand)
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    let lower = value.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+struct Assembler {
+    labels: HashMap<String, u16>,
+    // the first pass only measures instructions, before every label has an address
+    first_pass: bool,
+}
+
+impl Assembler {
+    // a number or label, which must fit in the given number of bits
+    fn value(&self, value: &str, bits: u32) -> Result<u16, String> {
+        let number = match parse_number(value) {
+            Some(number) => number,
+            None => match self.labels.get(value) {
+                Some(&address) => address as u32,
+                None if self.first_pass => 0,
+                None => return Err(format!("Unknown label {}", value)),
+            },
+        };
+
+        if number >= 1 << bits {
+            return Err(format!("{} does not fit in {} bits", value, bits));
+        }
+        Ok(number as u16)
+    }
+
+    fn line(&self, mnemonic: &str, operands: &[Operand]) -> Result<Vec<u8>, String> {
+        use Instruction::*;
+        use Operand::*;
+
+        let word = |instruction: Instruction, x: u16, y: u16, low: u16| {
+            Ok(vec![instruction.opcode() | x << 8 | y << 4 | low])
+        };
+
+        let words: Result<Vec<u16>, String> = match (mnemonic, operands) {
+            ("DB", values) => {
+                return values
+                    .iter()
+                    .map(|operand| match operand {
+                        Value(value) => self.value(value, 8).map(|byte| byte as u8),
+                        _ => Err("DB takes only numbers".to_string()),
+                    })
+                    .collect();
+            }
+            ("DW", values) => values
+                .iter()
+                .map(|operand| match operand {
+                    Value(value) => self.value(value, 16),
+                    _ => Err("DW takes only numbers and labels".to_string()),
+                })
+                .collect(),
+            ("SYS", [Value(nnn)]) => word(MachineCall, 0, 0, self.value(nnn, 12)?),
+            ("CLS", []) => word(Clear, 0, 0, 0),
+            ("RET", []) => word(PopStack, 0, 0, 0),
+            ("LOW", []) => word(LowResolution, 0, 0, 0),
+            ("TALL", []) => word(TallResolution, 0, 0, 0),
+            ("HIGH", []) => word(HighResolution, 0, 0, 0),
+            ("AUDIO", []) => word(LoadAudioPattern, 0, 0, 0),
+            ("JP", [Value(nnn)]) => word(SetProgramCounter, 0, 0, self.value(nnn, 12)?),
+            ("JP", [Register(0), Value(nnn)]) => {
+                word(SetProgramCounterOffset, 0, 0, self.value(nnn, 12)?)
+            }
+            ("CALL", [Value(nnn)]) => word(PushStackSetProgramCounter, 0, 0, self.value(nnn, 12)?),
+            ("SE", [Register(x), Register(y)]) => word(SkipIfEqualRegister, *x, *y, 0),
+            ("SE", [Register(x), Value(nn)]) => {
+                word(SkipIfEqualImmediate, *x, 0, self.value(nn, 8)?)
+            }
+            ("SNE", [Register(x), Register(y)]) => word(SkipIfNotEqualRegister, *x, *y, 0),
+            ("SNE", [Register(x), Value(nn)]) => {
+                word(SkipIfNotEqualImmediate, *x, 0, self.value(nn, 8)?)
+            }
+            ("LD", [Register(x), Register(y)]) => word(CopyFromRegisterToRegister, *x, *y, 0),
+            ("LD", [Register(x), Value(nn)]) => word(SetRegister, *x, 0, self.value(nn, 8)?),
+            ("LD", [Register(x), DelayTimer]) => word(CopyDelayTimer, *x, 0, 0),
+            ("LD", [Register(x), Key]) => word(WaitForKeyPress, *x, 0, 0),
+            ("LD", [Register(x), IndirectIndex]) => word(ReadFromMemory, *x, 0, 0),
+            ("LD", [DelayTimer, Register(x)]) => word(SetDelayTimer, *x, 0, 0),
+            ("LD", [SoundTimer, Register(x)]) => word(SetSoundTimer, *x, 0, 0),
+            ("LD", [Font, Register(x)]) => word(SetIndexRegisterToFontCharacter, *x, 0, 0),
+            ("LD", [Decimal, Register(x)]) => word(ConvertToDecimal, *x, 0, 0),
+            ("LD", [IndirectIndex, Register(x)]) => word(WriteToMemory, *x, 0, 0),
+            ("LD", [Index, Value(nnn)]) => word(SetIndexRegister, 0, 0, self.value(nnn, 12)?),
+            ("LD", [Index, Long(None)]) => word(SetIndexRegisterLong, 0, 0, 0),
+            ("LD", [Index, Long(Some(address))]) => Ok(vec![
+                SetIndexRegisterLong.opcode(),
+                self.value(address, 16)?,
+            ]),
+            ("ADD", [Register(x), Register(y)]) => word(Addition, *x, *y, 0),
+            ("ADD", [Register(x), Value(nn)]) => word(AddToRegister, *x, 0, self.value(nn, 8)?),
+            ("ADD", [Index, Register(x)]) => word(AddToIndexRegister, *x, 0, 0),
+            ("OR", [Register(x), Register(y)]) => word(LogicalOr, *x, *y, 0),
+            ("AND", [Register(x), Register(y)]) => word(LogicalAnd, *x, *y, 0),
+            ("XOR", [Register(x), Register(y)]) => word(LogicalXor, *x, *y, 0),
+            ("SUB", [Register(x), Register(y)]) => word(Subtraction, *x, *y, 0),
+            ("SHR", [Register(x), Register(y)]) => word(RightShift, *x, *y, 0),
+            ("SUBN", [Register(x), Register(y)]) => word(FlippedSubtraction, *x, *y, 0),
+            ("SHL", [Register(x), Register(y)]) => word(LeftShift, *x, *y, 0),
+            ("RND", [Register(x), Value(nn)]) => word(RandomNumber, *x, 0, self.value(nn, 8)?),
+            ("DRW", [Register(x), Register(y), Value(n)]) => word(Draw, *x, *y, self.value(n, 4)?),
+            ("SKP", [Register(x)]) => word(KeyDown, *x, 0, 0),
+            ("SKNP", [Register(x)]) => word(KeyNotDown, *x, 0, 0),
+            ("PLANE", [Value(planes)]) => word(SelectPlane, self.value(planes, 4)?, 0, 0),
+            ("PITCH", [Register(x)]) => word(SetPitch, *x, 0, 0),
+            _ => Err(format!("Unrecognised instruction {}", mnemonic)),
+        };
+
+        Ok(words?.iter().flat_map(|word| word.to_be_bytes()).collect())
+    }
+}
+
+// origin is the address the program will be loaded at, which labels are relative to
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AssembleError> {
+    let mut assembler = Assembler {
+        labels: HashMap::new(),
+        first_pass: true,
+    };
+
+    let mut program = Vec::new();
+    for pass in 0..2 {
+        assembler.first_pass = pass == 0;
+        program.clear();
+
+        for (index, line) in source.lines().enumerate() {
+            let error = |message| AssembleError {
+                line: index + 1,
+                message,
+            };
+
+            let mut line = line.split(';').next().unwrap_or("").trim();
+            if let Some((label, rest)) = line.split_once(':') {
+                let label = label.trim();
+                if assembler.first_pass {
+                    let address = origin as usize + program.len();
+                    if assembler
+                        .labels
+                        .insert(label.to_string(), address as u16)
+                        .is_some()
+                    {
+                        return Err(error(format!("Label {} is defined twice", label)));
+                    }
+                }
+                line = rest.trim();
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let operands: Vec<Operand> = operands
+                .split(',')
+                .map(str::trim)
+                .filter(|operand| !operand.is_empty())
+                .map(parse_operand)
+                .collect();
+
+            let bytes = assembler
+                .line(&mnemonic.to_ascii_uppercase(), &operands)
+                .map_err(error)?;
+            program.extend_from_slice(&bytes);
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::ParsedInstruction;
+    use crate::emulator::PROGRAM_START;
+
+    #[test]
+    fn disassembly_assembles_back_to_the_same_opcodes() {
+        let opcodes = [
+            0x0123, 0x00E0, 0x0230, 0x00EE, 0x00FE, 0x00FF, 0x1ABC, 0x2ABC, 0x3A12, 0x4A12, 0x5AB0,
+            0x6A12, 0x7A12, 0x8AB0, 0x8AB1, 0x8AB2, 0x8AB3, 0x8AB4, 0x8AB5, 0x8AB6, 0x8AB7, 0x8ABE,
+            0x9AB0, 0xAABC, 0xBABC, 0xCA12, 0xDAB5, 0xEA9E, 0xEAA1, 0xF000, 0xF201, 0xF002, 0xFA07,
+            0xFA0A, 0xFA15, 0xFA18, 0xFA1E, 0xFA29, 0xFA33, 0xFA3A, 0xFA55, 0xFA65,
+        ];
+        let source: Vec<String> = opcodes
+            .iter()
+            .map(|&opcode| ParsedInstruction::parse(opcode).unwrap().to_string())
+            .collect();
+
+        let program = assemble(&source.join("\n"), PROGRAM_START).unwrap();
+        let expected: Vec<u8> = opcodes
+            .iter()
+            .flat_map(|op: &u16| op.to_be_bytes())
+            .collect();
+        assert_eq!(program, expected);
+    }
+
+    #[test]
+    fn labels_resolve_to_load_addresses() {
+        let source = "
+            start:  LD V0, 5      ; count down from five
+            loop:   ADD V0, 0xFF
+                    SE V0, 0
+                    JP loop
+                    LD I, sprite
+                    JP start
+            sprite: DB 0b11110000, 0x90
+        ";
+        assert_eq!(
+            assemble(source, PROGRAM_START).unwrap(),
+            [0x60, 0x05, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x02, 0xA2, 0x0C, 0x12, 0x00, 0xF0, 0x90]
+        );
+    }
+
+    #[test]
+    fn unknown_label_is_an_error() {
+        assert_eq!(
+            assemble("CLS\nJP nowhere", PROGRAM_START),
+            Err(AssembleError {
+                line: 2,
+                message: "Unknown label nowhere".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use crate::emulator::Emulator;
+use rand::Rng;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::Sdl;
+use std::f32::consts::TAU;
+
+const BEEP_FREQUENCY: f32 = 440.0;
+const VOLUME: f32 = 0.2;
+const PATTERN_BITS: usize = 128;
+
+// shape of the beep. XO-CHIP patterns are always played as the square bits they describe
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+impl Waveform {
+    pub fn named(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            "noise" => Some(Waveform::Noise),
+            _ => None,
+        }
+    }
+
+    // one sample at a phase between 0 and 1, from -1 to 1
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Noise => rand::thread_rng().gen_range(-1.0..1.0),
+        }
+    }
+}
+
+struct Voice {
+    sample_rate: f32,
+    waveform: Waveform,
+    phase: f32,
+    playing: bool,
+    muted: bool, // silent whatever the sound timer says
+    pattern: Option<[u8; 16]>,
+    pattern_rate: f32, // pattern bits played per second
+}
+
+impl Voice {
+    fn sample(&self) -> f32 {
+        match self.pattern {
+            Some(pattern) => {
+                let bit = self.phase as usize % PATTERN_BITS;
+                if (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1 {
+                    VOLUME
+                } else {
+                    -VOLUME
+                }
+            }
+            None => self.waveform.sample(self.phase) * VOLUME,
+        }
+    }
+
+    fn advance(&mut self) {
+        match self.pattern {
+            Some(_) => {
+                self.phase =
+                    (self.phase + self.pattern_rate / self.sample_rate) % PATTERN_BITS as f32
+            }
+            None => self.phase = (self.phase + BEEP_FREQUENCY / self.sample_rate) % 1.0,
+        }
+    }
+}
+
+impl AudioCallback for Voice {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            if self.playing && !self.muted {
+                *sample = self.sample();
+                self.advance();
+            } else {
+                *sample = 0.0;
+            }
+        }
+    }
+}
+
+// plays the classic beep, or the XO-CHIP pattern buffer once a ROM has loaded one, while the sound timer is active
+pub struct Audio {
+    device: AudioDevice<Voice>,
+}
+
+impl Audio {
+    pub fn new(sdl_context: &Sdl, waveform: Waveform, muted: bool) -> Result<Self, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| Voice {
+            sample_rate: spec.freq as f32,
+            waveform,
+            phase: 0.0,
+            playing: false,
+            muted,
+            pattern: None,
+            pattern_rate: 0.0,
+        })?;
+        device.resume();
+
+        Ok(Audio { device })
+    }
+
+    // returns whether it's now muted
+    pub fn toggle_mute(&mut self) -> bool {
+        let mut voice = self.device.lock();
+        voice.muted = !voice.muted;
+        voice.muted
+    }
+
+    pub fn update(&mut self, emulator: &Emulator) {
+        let mut voice = self.device.lock();
+        voice.playing = emulator.beeping();
+        voice.pattern = emulator.audio_pattern;
+        voice.pattern_rate = pattern_rate(emulator.pitch);
+    }
+}
+
+// XO-CHIP playback rate: 4000Hz at the default pitch of 64, doubling every 48 steps
+fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveforms_peak_where_expected() {
+        assert_eq!(Waveform::Square.sample(0.25), 1.0);
+        assert_eq!(Waveform::Square.sample(0.75), -1.0);
+        assert!((Waveform::Sine.sample(0.25) - 1.0).abs() < 1e-6);
+        assert_eq!(Waveform::Triangle.sample(0.5), 1.0);
+        assert_eq!(Waveform::Triangle.sample(0.0), -1.0);
+        assert!((-1.0..1.0).contains(&Waveform::Noise.sample(0.0)));
+    }
+}
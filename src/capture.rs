@@ -0,0 +1,167 @@
+use crate::display::{Display, LOW_RES_HEIGHT, LOW_RES_WIDTH};
+use crate::emulator::Emulator;
+use crate::gif;
+use crate::headless::{self, Options};
+use crate::palette::Palette;
+use crate::png;
+use crate::replay::Input;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// recordings are capped at 60fps and stored at 256x128, which keeps GIFs small while staying legible
+const RECORD_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const RECORD_WIDTH: usize = LOW_RES_WIDTH * 4;
+const RECORD_HEIGHT: usize = LOW_RES_HEIGHT * 4;
+// browsers slow down GIF frames shorter than 2 hundredths of a second
+const MIN_FRAME_DELAY: u16 = 2;
+
+// the display as RGB bytes, each CHIP-8 pixel drawn as a scale x scale block
+pub fn rasterize(display: &Display, palette: &Palette, scale: u32) -> (u32, u32, Vec<u8>) {
+    let scale = scale as usize;
+    let width = display.width() * scale;
+    let height = display.height() * scale;
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for row in display.rows() {
+        let mut line = Vec::with_capacity(width * 3);
+        for &val in row.iter() {
+            let color = palette.colors[val as usize];
+            for _ in 0..scale {
+                line.extend_from_slice(&[color.r, color.g, color.b]);
+            }
+        }
+        for _ in 0..scale {
+            rgb.extend_from_slice(&line);
+        }
+    }
+
+    (width as u32, height as u32, rgb)
+}
+
+// the same size as --record's frames, big enough for a gallery thumbnail
+pub const CAPTURE_SCALE: u32 = 4;
+
+// a scale of 0 is taken as 1, since a 0x0 PNG isn't valid
+pub fn save_png(path: &Path, display: &Display, palette: &Palette, scale: u32) -> io::Result<()> {
+    let (width, height, rgb) = rasterize(display, palette, scale.max(1));
+    fs::write(path, png::encode(width, height, &rgb))
+}
+
+// runs headless up to the cycle, then saves the display as a PNG
+pub fn save_at_cycle(
+    emulator: Emulator,
+    input: Input,
+    cycle: u64,
+    instructions_per_second: u32,
+    path: &Path,
+    palette: &Palette,
+    scale: u32,
+) -> Result<(), String> {
+    let options = Options {
+        limit_cycles: Some(cycle),
+        timeout: None,
+        instructions_per_second,
+        ascii_frames: false,
+    };
+    let (emulator, result) = headless::emulate(emulator, &options, input);
+    if let Err(err) = result {
+        return Err(format!("Emulation halted before cycle {}: {}", cycle, err));
+    }
+    save_png(path, emulator.display(), palette, scale)
+        .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+}
+
+// screenshot-<unix time in ms>.png in the working directory
+pub fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or(0);
+    PathBuf::from(format!("screenshot-{}.png", millis))
+}
+
+// collects rendered frames for an animated GIF, written out once recording stops
+pub struct Recorder {
+    frames: Vec<gif::Frame>,
+    last_capture: Option<Instant>,
+    width: usize,
+    height: usize,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            frames: Vec::new(),
+            last_capture: None,
+            width: 0,
+            height: 0,
+        }
+    }
+
+    pub fn capture(&mut self, display: &Display) {
+        let now = Instant::now();
+        let since_last = self.last_capture.map(|last| now.duration_since(last));
+        if since_last.is_some_and(|elapsed| elapsed < RECORD_INTERVAL) {
+            return;
+        }
+
+        // the GIF canvas has a fixed size, so high-res frames are scaled up less and
+        // 64x64 frames are centred with background either side
+        let scale = (RECORD_WIDTH / display.width()).min(RECORD_HEIGHT / display.height());
+        let margin = (RECORD_WIDTH - display.width() * scale) / 2;
+        self.width = RECORD_WIDTH;
+        self.height = RECORD_HEIGHT;
+
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for row in display.rows() {
+            let mut line = vec![0; margin];
+            line.extend(row.iter().flat_map(|&val| std::iter::repeat_n(val, scale)));
+            line.resize(RECORD_WIDTH, 0);
+            for _ in 0..scale {
+                pixels.extend_from_slice(&line);
+            }
+        }
+
+        // an unchanged frame just extends how long the previous one is shown
+        if self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.pixels == pixels)
+        {
+            return;
+        }
+
+        if let (Some(previous), Some(elapsed)) = (self.frames.last_mut(), since_last) {
+            let hundredths = (elapsed.as_millis() / 10).min(u16::MAX as u128) as u16;
+            previous.delay = hundredths.max(MIN_FRAME_DELAY);
+        }
+
+        self.frames.push(gif::Frame { pixels, delay: 0 });
+        self.last_capture = Some(now);
+    }
+
+    pub fn save(mut self, path: &Path, palette: &Palette) -> io::Result<()> {
+        if let (Some(last), Some(last_capture)) = (self.frames.last_mut(), self.last_capture) {
+            let hundredths = (last_capture.elapsed().as_millis() / 10).min(u16::MAX as u128);
+            last.delay = (hundredths as u16).max(MIN_FRAME_DELAY);
+        }
+
+        let colors: Vec<[u8; 3]> = palette
+            .colors
+            .iter()
+            .map(|color| [color.r, color.g, color.b])
+            .collect();
+        fs::write(
+            path,
+            gif::encode(self.width as u16, self.height as u16, &colors, &self.frames),
+        )
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
@@ -0,0 +1,119 @@
+// several ROMs packed into one file behind a table of contents:
+//   "C8CART", then the entry count as a byte
+//   per entry, its name length as a byte, the name in UTF-8 and the ROM length as a big-endian u16
+//   the ROMs themselves, one after another in table order
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8] = b"C8CART";
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub rom: Vec<u8>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Cartridge {
+    pub entries: Vec<Entry>,
+}
+
+impl Cartridge {
+    pub fn new() -> Self {
+        Cartridge::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        Cartridge::parse(&bytes).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or("not a cartridge, expected it to start with C8CART")?;
+        let mut take = |count: usize| {
+            if rest.len() < count {
+                return Err("the cartridge is truncated".to_string());
+            }
+            let (taken, remaining) = rest.split_at(count);
+            rest = remaining;
+            Ok(taken)
+        };
+
+        let count = take(1)?[0];
+        let mut table = Vec::new();
+        for _ in 0..count {
+            let name_len = take(1)?[0] as usize;
+            let name = String::from_utf8(take(name_len)?.to_vec())
+                .map_err(|_| "an entry name is not valid UTF-8".to_string())?;
+            let len = take(2)?;
+            table.push((name, u16::from_be_bytes([len[0], len[1]]) as usize));
+        }
+
+        let mut cartridge = Cartridge::new();
+        for (name, len) in table {
+            let rom = take(len)?.to_vec();
+            cartridge.entries.push(Entry { name, rom });
+        }
+        Ok(cartridge)
+    }
+
+    pub fn push(&mut self, name: &str, rom: Vec<u8>) {
+        self.entries.push(Entry {
+            name: name.to_string(),
+            rom,
+        });
+    }
+
+    // names longer than 255 bytes are cut short, and there can be at most 255 entries. a ROM
+    // longer than the table's u16 length can hold is an error rather than a corrupt cartridge
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let entries = &self.entries[..self.entries.len().min(u8::MAX as usize)];
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(entries.len() as u8);
+        for entry in entries {
+            let mut end = entry.name.len().min(u8::MAX as usize);
+            while !entry.name.is_char_boundary(end) {
+                end -= 1;
+            }
+            let name = &entry.name.as_bytes()[..end];
+            let len = u16::try_from(entry.rom.len()).map_err(|_| {
+                format!(
+                    "{} is {} bytes, more than a cartridge entry can hold",
+                    entry.name,
+                    entry.rom.len()
+                )
+            })?;
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name);
+            bytes.extend_from_slice(&len.to_be_bytes());
+        }
+        for entry in entries {
+            bytes.extend_from_slice(&entry.rom);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cartridge_round_trips_through_bytes() {
+        let mut cartridge = Cartridge::new();
+        cartridge.push("pong.ch8", vec![0x00, 0xE0, 0x12, 0x00]);
+        cartridge.push("maze.ch8", vec![0xA2, 0x1E]);
+        let bytes = cartridge.to_bytes().unwrap();
+
+        assert_eq!(Cartridge::parse(&bytes), Ok(cartridge));
+        assert!(Cartridge::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Cartridge::parse(b"PK\x03\x04").is_err());
+
+        let mut oversized = Cartridge::new();
+        oversized.push("huge.ch8", vec![0; 65_536]);
+        assert!(oversized.to_bytes().is_err());
+    }
+}
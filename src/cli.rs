@@ -0,0 +1,222 @@
+use chip_8::quirks::{DrawMode, QuirkOverrides, ShiftMode};
+use chip_8::timer::{MAX_TIME_SCALE, MIN_TIME_SCALE};
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct Args {
+    pub rom: Option<PathBuf>, // the ROM to run, given as the one argument without a -- flag
+    pub cart: Option<PathBuf>, // a cartridge of several ROMs to run one of instead
+    pub entry: Option<usize>, // which of the cartridge's ROMs, counting from 1. asked for if not given
+    pub terminal: bool,
+    pub headless: bool,
+    pub vsync: bool,
+    pub pause_at_start: bool,
+    pub ghosting: bool,
+    pub decode_cache: bool,
+    pub xo_chip: bool,
+    pub ignore_machine_calls: bool,
+    pub halt_on_odd_pc: bool,
+    pub warn_low_writes: bool,
+    pub strict: bool,
+    pub profile: bool, // print how often each instruction ran on exit
+    pub preset: Option<String>,
+    pub quirks: QuirkOverrides, // --wrap-x, --no-wrap-x, --shift and so on
+    pub palette: Option<String>,
+    pub config: Option<PathBuf>, // per-game settings to use instead of the .toml beside the ROM
+    pub instructions_per_second: Option<u32>,
+    pub instructions_per_frame: Option<u32>, // pace by a fixed count each 60Hz frame instead of by interval
+    pub time_scale: Option<f64>,             // 0.5 runs instructions and timers at half speed
+    pub scale: Option<u32>, // window pixels per CHIP-8 pixel at the starting resolution
+    pub rewind_depth: usize,
+    pub hexdump: bool,
+    pub test_mode: bool,
+    pub ascii_frames: bool,
+    pub fill: Option<String>,
+    pub expect_frame: Option<PathBuf>, // run headless and compare the final frame against this one
+    pub quirks_report: bool, // run the ROM briefly and suggest quirks instead of playing it
+    pub watch_file: bool,
+    pub texture: bool,
+    pub dirty_render: bool,
+    pub slow_on_collision: bool,
+    pub wave: Option<String>,
+    pub mute: bool,
+    pub keymap: Option<String>,
+    pub record: Option<PathBuf>,
+    pub capture_at_cycle: Option<u64>, // run headless to this cycle and save the display to capture_out
+    pub capture_out: Option<PathBuf>,
+    pub load_address: Option<u16>,
+    pub font_address: Option<u16>,
+    pub seed: Option<u64>,
+    pub limit_cycles: Option<u64>,
+    pub timeout: Option<Duration>, // quit after this much wall-clock time, for batch runs
+    pub record_input: Option<PathBuf>,
+    pub replay_input: Option<PathBuf>,
+    pub record_demo: Option<PathBuf>, // like record_input, but bundled with the ROM and quirks
+    pub play: Option<PathBuf>,        // a demo to play back in place of a ROM
+    pub dump_memory: Option<PathBuf>,
+    pub watches: Vec<u16>,
+    pub breakpoints: Vec<u16>,
+    pub disassemble: Option<PathBuf>, // print a listing of this ROM instead of running it
+    pub assemble: Option<(PathBuf, PathBuf)>, // source and output, assembled instead of running a ROM
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut raw_args = env::args().skip(1);
+
+        while let Some(arg) = raw_args.next() {
+            // each on or off quirk as --wrap-x to turn it on or --no-wrap-x to turn it off
+            if let Some(name) = arg.strip_prefix("--") {
+                let (name, on) = match name.strip_prefix("no-") {
+                    Some(name) => (name, false),
+                    None => (name, true),
+                };
+                let flag = (!name.contains('_'))
+                    .then(|| args.quirks.flag_mut(&name.replace('-', "_")))
+                    .flatten();
+                if let Some(flag) = flag {
+                    *flag = Some(on);
+                    continue;
+                }
+            }
+
+            let mut value = || {
+                raw_args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--cart" => args.cart = Some(PathBuf::from(value()?)),
+                "--entry" => args.entry = Some(parse_positive(&value()?)? as usize),
+                "--terminal" => args.terminal = true,
+                "--headless" => args.headless = true,
+                "--vsync" => args.vsync = true,
+                "--pause-at-start" => args.pause_at_start = true,
+                "--ghosting" => args.ghosting = true,
+                "--decode-cache" => args.decode_cache = true,
+                "--xo-chip" => args.xo_chip = true,
+                "--ignore-machine-calls" => args.ignore_machine_calls = true,
+                "--halt-on-odd-pc" => args.halt_on_odd_pc = true,
+                "--warn-low-writes" => args.warn_low_writes = true,
+                "--strict" => args.strict = true,
+                "--preset" => args.preset = Some(value()?),
+                "--shift" => {
+                    let name = value()?;
+                    let shift = ShiftMode::named(&name).ok_or_else(|| {
+                        format!("Unknown shift mode {}, expected vy or in-place", name)
+                    })?;
+                    args.quirks.shift = Some(shift);
+                }
+                "--draw-mode" => {
+                    let name = value()?;
+                    let draw = DrawMode::named(&name)
+                        .ok_or_else(|| format!("Unknown draw mode {}, expected xor or or", name))?;
+                    args.quirks.draw = Some(draw);
+                }
+                "--palette" => args.palette = Some(value()?),
+                "--config" => args.config = Some(PathBuf::from(value()?)),
+                "--ips" => args.instructions_per_second = Some(parse_positive(&value()?)?),
+                "--ipf" => args.instructions_per_frame = Some(parse_positive(&value()?)?),
+                "--scale" => args.scale = Some(parse_positive(&value()?)?),
+                "--hexdump" => args.hexdump = true,
+                "--test-mode" => args.test_mode = true,
+                "--ascii-frames" => args.ascii_frames = true,
+                "--fill" => args.fill = Some(value()?),
+                "--quirks-report" => args.quirks_report = true,
+                "--expect-frame" => args.expect_frame = Some(PathBuf::from(value()?)),
+                "--watch-file" => args.watch_file = true,
+                "--texture" => args.texture = true,
+                "--dirty-render" => args.dirty_render = true,
+                "--slow-on-collision" => args.slow_on_collision = true,
+                "--time-scale" => args.time_scale = Some(parse_scale(&value()?)?),
+                "--profile" => args.profile = true,
+                "--rewind" => args.rewind_depth = parse_number(&value()?)?,
+                "--wave" => args.wave = Some(value()?),
+                "--mute" => args.mute = true,
+                "--keymap" => args.keymap = Some(value()?),
+                "--record" => args.record = Some(PathBuf::from(value()?)),
+                "--capture-at-cycle" => args.capture_at_cycle = Some(parse_number(&value()?)?),
+                "--capture-out" => args.capture_out = Some(PathBuf::from(value()?)),
+                "--dump-mem" => args.dump_memory = Some(PathBuf::from(value()?)),
+                "--load-address" => args.load_address = Some(parse_number(&value()?)?),
+                "--font-address" => args.font_address = Some(parse_number(&value()?)?),
+                "--record-input" => args.record_input = Some(PathBuf::from(value()?)),
+                "--replay-input" => args.replay_input = Some(PathBuf::from(value()?)),
+                "--record-demo" => args.record_demo = Some(PathBuf::from(value()?)),
+                "--play" => args.play = Some(PathBuf::from(value()?)),
+                "--timeout" => args.timeout = Some(parse_seconds(&value()?)?),
+                "--limit-cycles" => args.limit_cycles = Some(parse_number(&value()?)?),
+                "--seed" => args.seed = Some(parse_number(&value()?)?),
+                "--watch" => args.watches.push(parse_number(&value()?)?),
+                "--disassemble" => args.disassemble = Some(PathBuf::from(value()?)),
+                "--assemble" => {
+                    let source = PathBuf::from(value()?);
+                    args.assemble = Some((source, PathBuf::from(value()?)));
+                }
+                "--break" => args.breakpoints.push(parse_number(&value()?)?),
+                rom if !rom.starts_with('-') && args.rom.is_none() => {
+                    args.rom = Some(PathBuf::from(rom))
+                }
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        if args.instructions_per_frame.is_some() && args.test_mode {
+            return Err(
+                "--ipf and --test-mode pace instructions differently, pick one".to_string(),
+            );
+        }
+        if args.capture_at_cycle.is_some() != args.capture_out.is_some() {
+            return Err("--capture-at-cycle and --capture-out must be given together".to_string());
+        }
+        Ok(args)
+    }
+}
+
+// decimal, or hexadecimal with a 0x prefix
+fn parse_number<T: TryFrom<u64>>(value: &str) -> Result<T, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed
+        .ok()
+        .and_then(|number| T::try_from(number).ok())
+        .ok_or_else(|| format!("Invalid number {}", value))
+}
+
+fn parse_scale(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(scale) if (MIN_TIME_SCALE..=MAX_TIME_SCALE).contains(&scale) => Ok(scale),
+        _ => Err(format!(
+            "Invalid time scale {}, expected a number from {} to {}",
+            value, MIN_TIME_SCALE, MAX_TIME_SCALE
+        )),
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 => Duration::try_from_secs_f64(seconds)
+            .map_err(|err| format!("Invalid timeout {}: {}", value, err)),
+        _ => Err(format!(
+            "Invalid timeout {}, expected a number of seconds above zero",
+            value
+        )),
+    }
+}
+
+fn parse_positive(value: &str) -> Result<u32, String> {
+    match parse_number(value)? {
+        0 => Err(format!("{} must be above zero", value)),
+        number => Ok(number),
+    }
+}
@@ -1,15 +1,93 @@
+#[cfg(feature = "sdl")]
+use sdl2::controller::Button;
+#[cfg(feature = "sdl")]
 use sdl2::keyboard::Keycode;
+#[cfg(feature = "sdl")]
+use std::collections::HashMap;
+
+// the d-pad gives the 2/4/6/8 directions most games steer with, A the 5 many fire or select with
+#[cfg(feature = "sdl")]
+const DEFAULT_BUTTONS: [(Button, u8); 12] = [
+    (Button::DPadUp, 0x2),
+    (Button::DPadLeft, 0x4),
+    (Button::DPadRight, 0x6),
+    (Button::DPadDown, 0x8),
+    (Button::A, 0x5),
+    (Button::B, 0x0),
+    (Button::X, 0x7),
+    (Button::Y, 0x9),
+    (Button::LeftShoulder, 0x1),
+    (Button::RightShoulder, 0xC),
+    (Button::Back, 0xE),
+    (Button::Start, 0xF),
+];
+
+// which keyboard key stands for each CHIP-8 key, by letter or digit on the key
+//   cosmac (default)   natural
+//   1 2 3 4            0 1 2 3 4 5 6 7
+//   q w e r            8 9 a b c d e f
+//   a s d f
+//   z x c v
+// cosmac keeps the 4x4 shape of the COSMAC VIP keypad, 123C/456D/789E/A0BF. natural puts every key on
+// the keyboard key with its own name
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Keymap {
+    #[default]
+    Cosmac,
+    Natural,
+}
+
+impl Keymap {
+    pub fn named(name: &str) -> Option<Keymap> {
+        match name {
+            "cosmac" => Some(Keymap::Cosmac),
+            "natural" => Some(Keymap::Natural),
+            _ => None,
+        }
+    }
+
+    // the keyboard key for each CHIP-8 key 0x0 - 0xF, in order
+    fn layout(self) -> &'static [u8; 16] {
+        match self {
+            Keymap::Cosmac => b"x123qweasdzc4rfv",
+            Keymap::Natural => b"0123456789abcdef",
+        }
+    }
+
+    // the CHIP-8 key for a typed letter or digit
+    pub fn hex_for(self, key: u8) -> Option<u8> {
+        let key = key.to_ascii_lowercase();
+        self.layout()
+            .iter()
+            .position(|&mapped| mapped == key)
+            .map(|hex| hex as u8)
+    }
+}
 
 pub struct Controller {
     pressed: [bool; 16],
-    pub last_pressed: Option<u8>, // last key pressed that is still pressed. will not go back to keys previously pressed (chip-8 hardware not this advanced).
+    held: Vec<u8>,            // keys still pressed, in the order they went down
+    waiting: bool,            // an FX0A wait is in progress
+    pressed_during_wait: u16, // keys pressed since the wait began, one bit per key
+    released_key: Option<u8>, // key pressed and then released during the wait
+    pub keymap: Keymap,
+    #[cfg(feature = "sdl")]
+    buttons: HashMap<Button, u8>, // gamepad buttons to CHIP-8 keys
 }
 
+// only derivable without the sdl feature, when there are no default buttons to fill in
+#[cfg_attr(not(feature = "sdl"), allow(clippy::derivable_impls))]
 impl Default for Controller {
     fn default() -> Self {
         Controller {
             pressed: [false; 16],
-            last_pressed: None,
+            held: Vec::new(),
+            waiting: false,
+            pressed_during_wait: 0,
+            released_key: None,
+            keymap: Keymap::default(),
+            #[cfg(feature = "sdl")]
+            buttons: HashMap::from(DEFAULT_BUTTONS),
         }
     }
 }
@@ -19,46 +97,208 @@ impl Controller {
         Self::default()
     }
 
-    // TODO: support various mappings
-    fn map_to_hex(&self, key: Keycode) -> Option<u8> {
-        match key {
-            Keycode::NUM_1 => Some(0x1),
-            Keycode::NUM_2 => Some(0x2),
-            Keycode::NUM_3 => Some(0x3),
-            Keycode::NUM_4 => Some(0xC),
-            Keycode::Q => Some(0x4),
-            Keycode::W => Some(0x5),
-            Keycode::E => Some(0x6),
-            Keycode::R => Some(0xD),
-            Keycode::A => Some(0x7),
-            Keycode::S => Some(0x8),
-            Keycode::D => Some(0x9),
-            Keycode::F => Some(0xE),
-            Keycode::Z => Some(0xA),
-            Keycode::X => Some(0x0),
-            Keycode::C => Some(0xB),
-            Keycode::V => Some(0xF),
-            _ => None,
+    // press/release by CHIP-8 key (0x0 - 0xF) for frontends that don't use SDL keycodes
+    pub fn press(&mut self, hex: u8) {
+        if let Some(pressed) = self.pressed.get_mut(hex as usize) {
+            *pressed = true;
+            self.held.retain(|&key| key != hex);
+            self.held.push(hex);
+            if self.waiting {
+                self.pressed_during_wait |= 1 << hex;
+            }
+        }
+    }
+
+    pub fn release(&mut self, hex: u8) {
+        if let Some(pressed) = self.pressed.get_mut(hex as usize) {
+            *pressed = false;
+            self.held.retain(|&key| key != hex);
+            if self.waiting && self.pressed_during_wait & (1 << hex) != 0 {
+                self.released_key = Some(hex);
+            }
+        }
+    }
+
+    // one bit per key, key 0x0 in the lowest bit
+    pub fn pressed_mask(&self) -> u16 {
+        self.pressed
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (key, &pressed)| mask | (pressed as u16) << key)
+    }
+
+    // presses and releases whichever keys differ from the mask, as if each had been pressed or
+    // released in turn, so an FX0A wait sees them the same way
+    pub fn set_mask(&mut self, mask: u16) {
+        for key in 0..16u8 {
+            let pressed = mask & (1 << key) != 0;
+            if pressed != self.is_key_pressed(key) {
+                if pressed {
+                    self.press(key);
+                } else {
+                    self.release(key);
+                }
+            }
+        }
+    }
+
+    // the most recently pressed key still held. releasing it falls back to the one pressed before
+    pub fn last_pressed(&self) -> Option<u8> {
+        self.held.last().copied()
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        *self.pressed.get(key as usize).unwrap_or(&false)
+    }
+
+    // an FX0A wait has begun and no key has ended it yet
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    // called on every cycle FX0A is blocked. like the COSMAC VIP, a key only counts once it has been
+    // pressed and released after the wait began, so a held key doesn't satisfy successive waits
+    pub fn wait_for_key_release(&mut self) -> Option<u8> {
+        if !self.waiting {
+            self.waiting = true;
+            self.pressed_during_wait = 0;
+            self.released_key = None;
         }
+
+        let key = self.released_key.take();
+        if key.is_some() {
+            self.waiting = false;
+        }
+        key
+    }
+}
+
+// keyboard and gamepad mapping for the SDL frontend
+#[cfg(feature = "sdl")]
+impl Controller {
+    // SDL keycodes for letters and digits are their lowercase ASCII
+    pub fn map_to_hex(&self, key: Keycode) -> Option<u8> {
+        u8::try_from(key.into_i32())
+            .ok()
+            .and_then(|key| self.keymap.hex_for(key))
+    }
+
+    pub fn map_button(&self, button: Button) -> Option<u8> {
+        self.buttons.get(&button).copied()
+    }
+
+    // replaces whatever the button was mapped to
+    pub fn bind_button(&mut self, button: Button, hex: u8) {
+        self.buttons.insert(button, hex);
     }
 
     pub fn press_key(&mut self, key: Keycode) {
         if let Some(hex) = self.map_to_hex(key) {
-            self.pressed[hex as usize] = true;
-            self.last_pressed = Some(hex);
+            self.press(hex);
         }
     }
 
     pub fn release_key(&mut self, key: Keycode) {
         if let Some(hex) = self.map_to_hex(key) {
-            self.pressed[hex as usize] = false;
-            if Some(hex) == self.last_pressed {
-                self.last_pressed = None;
-            }
+            self.release(hex);
         }
     }
+}
 
-    pub fn is_key_pressed(&self, key: u8) -> bool {
-        *self.pressed.get(key as usize).unwrap_or(&false)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_returns_key_after_release() {
+        let mut controller = Controller::new();
+        assert_eq!(controller.wait_for_key_release(), None);
+
+        controller.press(0x5);
+        assert_eq!(controller.wait_for_key_release(), None);
+
+        controller.release(0x5);
+        assert_eq!(controller.wait_for_key_release(), Some(0x5));
+    }
+
+    #[test]
+    fn key_held_before_wait_is_ignored() {
+        let mut controller = Controller::new();
+        controller.press(0x5);
+        assert_eq!(controller.wait_for_key_release(), None);
+
+        controller.release(0x5);
+        assert_eq!(controller.wait_for_key_release(), None);
+    }
+
+    #[test]
+    fn last_pressed_falls_back_to_earlier_held_keys() {
+        let mut controller = Controller::new();
+        controller.press(0x1);
+        controller.press(0x2);
+        controller.press(0x3);
+        assert_eq!(controller.last_pressed(), Some(0x3));
+
+        controller.release(0x3);
+        assert_eq!(controller.last_pressed(), Some(0x2));
+        controller.release(0x1);
+        assert_eq!(controller.last_pressed(), Some(0x2));
+
+        // pressing a held key again moves it to the top
+        controller.press(0x5);
+        controller.press(0x2);
+        controller.release(0x2);
+        assert_eq!(controller.last_pressed(), Some(0x5));
+        controller.release(0x5);
+        assert_eq!(controller.last_pressed(), None);
+    }
+
+    #[test]
+    fn mask_reads_and_drives_the_keypad() {
+        let mut controller = Controller::new();
+        controller.press(0x0);
+        controller.press(0xF);
+        assert_eq!(controller.pressed_mask(), 0x8001);
+
+        controller.wait_for_key_release();
+        controller.set_mask(0x0012);
+        assert!(controller.is_key_pressed(0x1) && controller.is_key_pressed(0x4));
+        assert!(!controller.is_key_pressed(0xF));
+        assert_eq!(controller.pressed_mask(), 0x0012);
+
+        controller.set_mask(0x0002);
+        assert_eq!(controller.wait_for_key_release(), Some(0x4));
+    }
+
+    #[test]
+    fn keymaps_place_keys_differently() {
+        assert_eq!(Keymap::Cosmac.hex_for(b'4'), Some(0xC));
+        assert_eq!(Keymap::Cosmac.hex_for(b'V'), Some(0xF));
+        assert_eq!(Keymap::Natural.hex_for(b'4'), Some(0x4));
+        assert_eq!(Keymap::Natural.hex_for(b'c'), Some(0xC));
+        assert_eq!(Keymap::Natural.hex_for(b'q'), None);
+    }
+
+    #[test]
+    #[cfg(feature = "sdl")]
+    fn buttons_can_be_rebound() {
+        let mut controller = Controller::new();
+        assert_eq!(controller.map_button(Button::DPadUp), Some(0x2));
+        assert_eq!(controller.map_button(Button::Guide), None);
+
+        controller.bind_button(Button::DPadUp, 0x1);
+        assert_eq!(controller.map_button(Button::DPadUp), Some(0x1));
+    }
+
+    #[test]
+    fn each_wait_needs_a_new_press() {
+        let mut controller = Controller::new();
+        controller.wait_for_key_release();
+        controller.press(0xA);
+        controller.release(0xA);
+        assert_eq!(controller.wait_for_key_release(), Some(0xA));
+
+        controller.press(0xA);
+        assert_eq!(controller.wait_for_key_release(), None);
     }
 }
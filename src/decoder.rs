@@ -1,6 +1,12 @@
-#[derive(Debug)]
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Instruction {
+    MachineCall, // 0NNN, a call into COSMAC VIP machine code which can't be emulated
     Clear,
+    LowResolution,
+    TallResolution,
+    HighResolution,
     PopStack,
     SetProgramCounter,
     PushStackSetProgramCounter,
@@ -20,6 +26,7 @@ pub enum Instruction {
     FlippedSubtraction,
     LeftShift,
     SetIndexRegister,
+    SetIndexRegisterLong,
     SetProgramCounterOffset,
     RandomNumber,
     Draw,
@@ -28,15 +35,18 @@ pub enum Instruction {
     CopyDelayTimer,
     SetDelayTimer,
     SetSoundTimer,
+    LoadAudioPattern,
+    SetPitch,
     AddToIndexRegister,
     WaitForKeyPress,
+    SelectPlane,
     SetIndexRegisterToFontCharacter,
     ConvertToDecimal,
     WriteToMemory,
     ReadFromMemory,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct ParsedInstruction {
     pub raw_instruction: u16,
     pub instruction: Instruction,
@@ -47,20 +57,40 @@ pub struct ParsedInstruction {
     pub nnn: u16,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct DecodeError {
+    pub raw_instruction: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid instruction {:04x}", self.raw_instruction)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
 impl ParsedInstruction {
-    pub fn parse(raw_instruction: u16) -> Self {
+    pub fn parse(raw_instruction: u16) -> Result<Self, DecodeError> {
         let first_nibble: u8 = ((raw_instruction & 0xF000) >> 12) as u8;
         let n = (raw_instruction & 0x000F) as u8;
         let nn = (raw_instruction & 0x00FF) as u8;
         let instruction = match raw_instruction {
             0x00E0 => Instruction::Clear,
             0x00EE => Instruction::PopStack,
+            0x00FE => Instruction::LowResolution,
+            0x0230 => Instruction::TallResolution,
+            0x00FF => Instruction::HighResolution,
+            0xF000 => Instruction::SetIndexRegisterLong,
+            0xF002 => Instruction::LoadAudioPattern,
             _ => match first_nibble {
+                0x0 => Instruction::MachineCall,
                 0x1 => Instruction::SetProgramCounter,
                 0x2 => Instruction::PushStackSetProgramCounter,
                 0x3 => Instruction::SkipIfEqualImmediate,
                 0x4 => Instruction::SkipIfNotEqualImmediate,
-                0x5 => Instruction::SkipIfEqualRegister,
+                // only defined with a zero low nibble. XO-CHIP's 5XY2 and 5XY3 aren't supported
+                0x5 if n == 0 => Instruction::SkipIfEqualRegister,
                 0x6 => Instruction::SetRegister,
                 0x7 => Instruction::AddToRegister,
                 0x8 => match n {
@@ -73,9 +103,9 @@ impl ParsedInstruction {
                     0x6 => Instruction::RightShift,
                     0x7 => Instruction::FlippedSubtraction,
                     0xE => Instruction::LeftShift,
-                    _ => panic!("Invalid instruction {:x}", raw_instruction),
+                    _ => return Err(DecodeError { raw_instruction }),
                 },
-                0x9 => Instruction::SkipIfNotEqualRegister,
+                0x9 if n == 0 => Instruction::SkipIfNotEqualRegister,
                 0xA => Instruction::SetIndexRegister,
                 0xB => Instruction::SetProgramCounterOffset,
                 0xC => Instruction::RandomNumber,
@@ -83,9 +113,10 @@ impl ParsedInstruction {
                 0xE => match nn {
                     0x9E => Instruction::KeyDown,
                     0xA1 => Instruction::KeyNotDown,
-                    _ => panic!("Invalid instruction {:x}", raw_instruction),
+                    _ => return Err(DecodeError { raw_instruction }),
                 },
                 0xF => match nn {
+                    0x01 => Instruction::SelectPlane,
                     0x07 => Instruction::CopyDelayTimer,
                     0x0A => Instruction::WaitForKeyPress,
                     0x15 => Instruction::SetDelayTimer,
@@ -93,15 +124,16 @@ impl ParsedInstruction {
                     0x1E => Instruction::AddToIndexRegister,
                     0x29 => Instruction::SetIndexRegisterToFontCharacter,
                     0x33 => Instruction::ConvertToDecimal,
+                    0x3A => Instruction::SetPitch,
                     0x55 => Instruction::WriteToMemory,
                     0x65 => Instruction::ReadFromMemory,
-                    _ => panic!("Invalid instruction {:x}", raw_instruction),
+                    _ => return Err(DecodeError { raw_instruction }),
                 },
-                _ => panic!("Invalid instruction {:x}", raw_instruction),
+                _ => return Err(DecodeError { raw_instruction }),
             },
         };
 
-        ParsedInstruction {
+        Ok(ParsedInstruction {
             raw_instruction,
             instruction,
             x: ((raw_instruction & 0x0F00) >> 8) as usize,
@@ -109,6 +141,262 @@ impl ParsedInstruction {
             n,
             nn,
             nnn: raw_instruction & 0x0FFF,
+        })
+    }
+}
+
+// every two-byte word of a program paired with its address, whether or not it decodes. a trailing odd
+// byte is decoded with a zero low byte. addresses are usize so a file bigger than memory still lists
+pub fn disassemble(
+    program: &[u8],
+    start: u16,
+) -> impl Iterator<Item = (usize, Result<ParsedInstruction, DecodeError>)> + '_ {
+    program.chunks(2).enumerate().map(move |(index, bytes)| {
+        let raw_instruction = (bytes[0] as u16) << 8 | *bytes.get(1).unwrap_or(&0) as u16;
+        let address = start as usize + index * 2;
+        (address, ParsedInstruction::parse(raw_instruction))
+    })
+}
+
+// disassemble as text, one line per word: its address, raw bytes and mnemonic. words that don't
+// decode are data, or a sprite, and are listed as DB so the assembler can take them back
+pub fn listing(program: &[u8], start: u16) -> Vec<String> {
+    disassemble(program, start)
+        .map(|(address, result)| {
+            let offset = address - start as usize;
+            let raw: Vec<String> = program[offset..program.len().min(offset + 2)]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            match result {
+                Ok(instruction) => {
+                    format!("{:#05X}  {:<4}  {}", address, raw.concat(), instruction)
+                }
+                Err(_) => format!(
+                    "{:#05X}  {:<4}  DB {}",
+                    address,
+                    raw.concat(),
+                    raw.iter()
+                        .map(|byte| format!("0x{}", byte))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        })
+        .collect()
+}
+
+pub const INSTRUCTION_COUNT: usize = 42;
+
+impl Instruction {
+    // every instruction, in declaration order so `instruction as usize` indexes into it
+    pub const ALL: [Instruction; INSTRUCTION_COUNT] = [
+        Instruction::MachineCall,
+        Instruction::Clear,
+        Instruction::LowResolution,
+        Instruction::TallResolution,
+        Instruction::HighResolution,
+        Instruction::PopStack,
+        Instruction::SetProgramCounter,
+        Instruction::PushStackSetProgramCounter,
+        Instruction::SkipIfEqualImmediate,
+        Instruction::SkipIfNotEqualImmediate,
+        Instruction::SkipIfEqualRegister,
+        Instruction::SkipIfNotEqualRegister,
+        Instruction::SetRegister,
+        Instruction::AddToRegister,
+        Instruction::CopyFromRegisterToRegister,
+        Instruction::LogicalOr,
+        Instruction::LogicalAnd,
+        Instruction::LogicalXor,
+        Instruction::Addition,
+        Instruction::Subtraction,
+        Instruction::RightShift,
+        Instruction::FlippedSubtraction,
+        Instruction::LeftShift,
+        Instruction::SetIndexRegister,
+        Instruction::SetIndexRegisterLong,
+        Instruction::SetProgramCounterOffset,
+        Instruction::RandomNumber,
+        Instruction::Draw,
+        Instruction::KeyDown,
+        Instruction::KeyNotDown,
+        Instruction::CopyDelayTimer,
+        Instruction::SetDelayTimer,
+        Instruction::SetSoundTimer,
+        Instruction::LoadAudioPattern,
+        Instruction::SetPitch,
+        Instruction::AddToIndexRegister,
+        Instruction::WaitForKeyPress,
+        Instruction::SelectPlane,
+        Instruction::SetIndexRegisterToFontCharacter,
+        Instruction::ConvertToDecimal,
+        Instruction::WriteToMemory,
+        Instruction::ReadFromMemory,
+    ];
+
+    // the opcode with every operand field zeroed
+    pub fn opcode(self) -> u16 {
+        match self {
+            Instruction::MachineCall => 0x0000,
+            Instruction::Clear => 0x00E0,
+            Instruction::PopStack => 0x00EE,
+            Instruction::LowResolution => 0x00FE,
+            Instruction::TallResolution => 0x0230,
+            Instruction::HighResolution => 0x00FF,
+            Instruction::SetProgramCounter => 0x1000,
+            Instruction::PushStackSetProgramCounter => 0x2000,
+            Instruction::SkipIfEqualImmediate => 0x3000,
+            Instruction::SkipIfNotEqualImmediate => 0x4000,
+            Instruction::SkipIfEqualRegister => 0x5000,
+            Instruction::SetRegister => 0x6000,
+            Instruction::AddToRegister => 0x7000,
+            Instruction::CopyFromRegisterToRegister => 0x8000,
+            Instruction::LogicalOr => 0x8001,
+            Instruction::LogicalAnd => 0x8002,
+            Instruction::LogicalXor => 0x8003,
+            Instruction::Addition => 0x8004,
+            Instruction::Subtraction => 0x8005,
+            Instruction::RightShift => 0x8006,
+            Instruction::FlippedSubtraction => 0x8007,
+            Instruction::LeftShift => 0x800E,
+            Instruction::SkipIfNotEqualRegister => 0x9000,
+            Instruction::SetIndexRegister => 0xA000,
+            Instruction::SetProgramCounterOffset => 0xB000,
+            Instruction::RandomNumber => 0xC000,
+            Instruction::Draw => 0xD000,
+            Instruction::KeyDown => 0xE09E,
+            Instruction::KeyNotDown => 0xE0A1,
+            Instruction::SetIndexRegisterLong => 0xF000,
+            Instruction::SelectPlane => 0xF001,
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::CopyDelayTimer => 0xF007,
+            Instruction::WaitForKeyPress => 0xF00A,
+            Instruction::SetDelayTimer => 0xF015,
+            Instruction::SetSoundTimer => 0xF018,
+            Instruction::AddToIndexRegister => 0xF01E,
+            Instruction::SetIndexRegisterToFontCharacter => 0xF029,
+            Instruction::ConvertToDecimal => 0xF033,
+            Instruction::SetPitch => 0xF03A,
+            Instruction::WriteToMemory => 0xF055,
+            Instruction::ReadFromMemory => 0xF065,
         }
     }
 }
+
+// mnemonics in the style of Cowgod's technical reference, which the assembler reads back
+impl fmt::Display for ParsedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (x, y, n, nn, nnn) = (self.x, self.y, self.n, self.nn, self.nnn);
+        match self.instruction {
+            Instruction::MachineCall => write!(f, "SYS {:#05X}", nnn),
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::PopStack => write!(f, "RET"),
+            Instruction::LowResolution => write!(f, "LOW"),
+            Instruction::TallResolution => write!(f, "TALL"),
+            Instruction::HighResolution => write!(f, "HIGH"),
+            Instruction::SetProgramCounter => write!(f, "JP {:#05X}", nnn),
+            Instruction::PushStackSetProgramCounter => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SkipIfEqualImmediate => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipIfNotEqualImmediate => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipIfEqualRegister => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SkipIfNotEqualRegister => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetRegister => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            Instruction::AddToRegister => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Instruction::CopyFromRegisterToRegister => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::LogicalOr => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::LogicalAnd => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::LogicalXor => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Addition => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Subtraction => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::RightShift => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::FlippedSubtraction => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::LeftShift => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SetIndexRegister => write!(f, "LD I, {:#05X}", nnn),
+            // the address is the following word, which is disassembled separately
+            Instruction::SetIndexRegisterLong => write!(f, "LD I, LONG"),
+            Instruction::SetProgramCounterOffset => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::RandomNumber => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Instruction::Draw => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::KeyDown => write!(f, "SKP V{:X}", x),
+            Instruction::KeyNotDown => write!(f, "SKNP V{:X}", x),
+            Instruction::CopyDelayTimer => write!(f, "LD V{:X}, DT", x),
+            Instruction::SetDelayTimer => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer => write!(f, "LD ST, V{:X}", x),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::SetPitch => write!(f, "PITCH V{:X}", x),
+            Instruction::AddToIndexRegister => write!(f, "ADD I, V{:X}", x),
+            Instruction::WaitForKeyPress => write!(f, "LD V{:X}, K", x),
+            Instruction::SelectPlane => write!(f, "PLANE {}", x),
+            Instruction::SetIndexRegisterToFontCharacter => write!(f, "LD F, V{:X}", x),
+            Instruction::ConvertToDecimal => write!(f, "LD B, V{:X}", x),
+            Instruction::WriteToMemory => write!(f, "LD [I], V{:X}", x),
+            Instruction::ReadFromMemory => write!(f, "LD V{:X}, [I]", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_instructions_are_in_discriminant_order() {
+        for (index, instruction) in Instruction::ALL.iter().enumerate() {
+            assert_eq!(*instruction as usize, index);
+        }
+    }
+
+    #[test]
+    fn listing_shows_data_as_bytes() {
+        assert_eq!(
+            listing(&[0x00, 0xE0, 0xFF, 0xFF, 0x12], 0x200),
+            vec![
+                "0x200  00E0  CLS",
+                "0x202  FFFF  DB 0xFF, 0xFF",
+                "0x204  12    JP 0x200",
+            ]
+        );
+    }
+
+    #[test]
+    fn register_skips_need_a_zero_low_nibble() {
+        assert_eq!(
+            ParsedInstruction::parse(0x5120).unwrap().instruction,
+            Instruction::SkipIfEqualRegister
+        );
+        assert_eq!(
+            ParsedInstruction::parse(0x9120).unwrap().instruction,
+            Instruction::SkipIfNotEqualRegister
+        );
+        for n in 0x1..=0xF {
+            for raw_instruction in [0x5120 | n, 0x9120 | n] {
+                assert_eq!(
+                    ParsedInstruction::parse(raw_instruction).unwrap_err(),
+                    DecodeError { raw_instruction }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn disassemble_pairs_addresses_with_decodes() {
+        let listing: Vec<String> = disassemble(&[0x00, 0xE0, 0xFF, 0xFF, 0x12], 0x200)
+            .map(|(address, result)| match result {
+                Ok(instruction) => format!("{:03x} {}", address, instruction),
+                Err(err) => format!("{:03x} {}", address, err),
+            })
+            .collect();
+        assert_eq!(
+            listing,
+            ["200 CLS", "202 Invalid instruction ffff", "204 JP 0x200"]
+        );
+    }
+
+    #[test]
+    fn disassemble_counts_addresses_past_the_u16_range() {
+        let program = vec![0; 70_000];
+        let (address, _) = disassemble(&program, 0x200).last().unwrap();
+        assert_eq!(address, 0x200 + 69_998);
+    }
+}
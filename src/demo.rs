@@ -0,0 +1,234 @@
+// a .c8demo bundles everything a run depends on: the ROM, the memory layout and quirks it ran with,
+// and the seed and input timeline from replay. playing one back reproduces the run exactly
+//   chip-8 demo
+//   memory_size 4096
+//   load_address 0x200
+//   font_address 0x50
+//   max_stack_depth 16
+//   ignore_machine_calls false
+//   halt_on_odd_pc false
+//   wrap_x false
+//   ...
+//   shift vy
+//   draw xor
+//   rom 00E0A22A...
+//   seed 7
+//   120 press a
+use crate::emulator::{Config, MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use crate::quirks::{DrawMode, Quirks, ShiftMode};
+use crate::replay::InputLog;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HEADER: &str = "chip-8 demo";
+
+#[derive(Debug, PartialEq)]
+pub struct Demo {
+    pub rom: Vec<u8>,
+    pub memory_size: usize,
+    pub load_address: u16,
+    pub font_address: u16,
+    pub max_stack_depth: usize,
+    pub ignore_machine_calls: bool,
+    pub halt_on_odd_pc: bool,
+    pub quirks: Quirks,
+    pub input: InputLog, // holds the seed too
+}
+
+impl Demo {
+    // an empty timeline for a run starting with this ROM and config
+    pub fn new(rom: Vec<u8>, config: &Config) -> Self {
+        Demo {
+            rom,
+            memory_size: config.memory_size,
+            load_address: config.load_address,
+            font_address: config.font_address,
+            max_stack_depth: config.max_stack_depth,
+            ignore_machine_calls: config.ignore_machine_calls,
+            halt_on_odd_pc: config.halt_on_odd_pc,
+            quirks: config.quirks,
+            input: InputLog::new(config.seed),
+        }
+    }
+
+    // replaces whatever the command line chose for the settings the demo was recorded with
+    pub fn apply(&self, config: &mut Config) {
+        config.memory_size = self.memory_size;
+        config.load_address = self.load_address;
+        config.font_address = self.font_address;
+        config.max_stack_depth = self.max_stack_depth;
+        config.ignore_machine_calls = self.ignore_machine_calls;
+        config.halt_on_odd_pc = self.halt_on_odd_pc;
+        config.quirks = self.quirks;
+        config.seed = self.input.seed;
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        Demo::parse(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut lines = contents.lines().map(str::trim);
+        if lines.next() != Some(HEADER) {
+            return Err("not a demo, expected it to start with `chip-8 demo`".to_string());
+        }
+
+        let mut demo = Demo::new(Vec::new(), &Config::default());
+        let mut rom = None;
+        // anything that isn't a setting belongs to the input log
+        let mut events = String::new();
+        for line in lines {
+            let invalid = || format!("invalid demo line {}", line);
+            let flag = |value: &str| value.parse::<bool>().map_err(|_| invalid());
+            let number = |value: &str| {
+                let parsed = match value.strip_prefix("0x") {
+                    Some(hex) => usize::from_str_radix(hex, 16),
+                    None => value.parse(),
+                };
+                parsed.map_err(|_| invalid())
+            };
+
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                // only the sizes --xo-chip picks between, so a demo can't ask for a huge allocation
+                ["memory_size", value] => match number(value)? {
+                    size @ (MEMORY_SIZE | XO_CHIP_MEMORY_SIZE) => demo.memory_size = size,
+                    _ => return Err(invalid()),
+                },
+                ["load_address", value] => {
+                    demo.load_address = number(value)?.try_into().map_err(|_| invalid())?
+                }
+                ["font_address", value] => {
+                    demo.font_address = number(value)?.try_into().map_err(|_| invalid())?
+                }
+                ["max_stack_depth", value] => demo.max_stack_depth = number(value)?,
+                ["ignore_machine_calls", value] => demo.ignore_machine_calls = flag(value)?,
+                ["halt_on_odd_pc", value] => demo.halt_on_odd_pc = flag(value)?,
+                ["wrap_x", value] => demo.quirks.wrap_x = flag(value)?,
+                ["wrap_y", value] => demo.quirks.wrap_y = flag(value)?,
+                ["clip_start", value] => demo.quirks.clip_start = flag(value)?,
+                ["wrap_index", value] => demo.quirks.wrap_index = flag(value)?,
+                ["jump_uses_vx", value] => demo.quirks.jump_uses_vx = flag(value)?,
+                ["keep_index", value] => demo.quirks.keep_index = flag(value)?,
+                ["keep_vf", value] => demo.quirks.keep_vf = flag(value)?,
+                ["shift", value] => {
+                    demo.quirks.shift = ShiftMode::named(value).ok_or_else(invalid)?
+                }
+                ["draw", value] => demo.quirks.draw = DrawMode::named(value).ok_or_else(invalid)?,
+                ["rom", hex] => rom = Some(parse_hex(hex).ok_or_else(invalid)?),
+                _ => {
+                    events.push_str(line);
+                    events.push('\n');
+                }
+            }
+        }
+
+        demo.rom = rom.ok_or("missing the rom line")?;
+        demo.input = InputLog::parse(&events)?;
+        Ok(demo)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut contents = format!("{}\n", HEADER);
+        contents.push_str(&format!("memory_size {}\n", self.memory_size));
+        contents.push_str(&format!("load_address {:#05x}\n", self.load_address));
+        contents.push_str(&format!("font_address {:#04x}\n", self.font_address));
+        contents.push_str(&format!("max_stack_depth {}\n", self.max_stack_depth));
+        contents.push_str(&format!(
+            "ignore_machine_calls {}\n",
+            self.ignore_machine_calls
+        ));
+        contents.push_str(&format!("halt_on_odd_pc {}\n", self.halt_on_odd_pc));
+        contents.push_str(&format!("wrap_x {}\n", self.quirks.wrap_x));
+        contents.push_str(&format!("wrap_y {}\n", self.quirks.wrap_y));
+        contents.push_str(&format!("clip_start {}\n", self.quirks.clip_start));
+        contents.push_str(&format!("wrap_index {}\n", self.quirks.wrap_index));
+        contents.push_str(&format!("jump_uses_vx {}\n", self.quirks.jump_uses_vx));
+        contents.push_str(&format!("keep_index {}\n", self.quirks.keep_index));
+        contents.push_str(&format!("keep_vf {}\n", self.quirks.keep_vf));
+        contents.push_str(&format!("shift {}\n", self.quirks.shift.name()));
+        contents.push_str(&format!("draw {}\n", self.quirks.draw.name()));
+        contents.push_str("rom ");
+        for byte in &self.rom {
+            contents.push_str(&format!("{:02X}", byte));
+        }
+        contents.push('\n');
+        contents.push_str(&self.input.to_text());
+        contents
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|start| u8::from_str_radix(hex.get(start..start + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::quirks;
+    use crate::replay::{Input, InputEvent};
+
+    #[test]
+    fn demo_round_trips_through_text() {
+        let config = Config {
+            load_address: 0x600,
+            max_stack_depth: 12,
+            ignore_machine_calls: true,
+            halt_on_odd_pc: true,
+            seed: Some(42),
+            quirks: quirks::XO_CHIP,
+            ..Config::default()
+        };
+        let mut demo = Demo::new(vec![0x00, 0xE0, 0x12, 0x00], &config);
+        demo.input.record(5, InputEvent::Press(0xB));
+        demo.input.record(9, InputEvent::Tick);
+
+        assert_eq!(Demo::parse(&demo.to_text()), Ok(demo));
+        assert!(Demo::parse("120 press a").is_err());
+        assert!(Demo::parse("chip-8 demo\nmemory_size 99999999999\nrom 00E0").is_err());
+    }
+
+    #[test]
+    fn playing_a_demo_repeats_the_run() {
+        // RND V0, FF then wait for a key into V1
+        let rom = vec![0xC0, 0xFF, 0xF1, 0x0A, 0x12, 0x04];
+        let mut demo = Demo::new(
+            rom.clone(),
+            &Config {
+                seed: Some(3),
+                ..Config::default()
+            },
+        );
+        demo.input.record(3, InputEvent::Press(0x7));
+        demo.input.record(5, InputEvent::Release(0x7));
+        let demo = Demo::parse(&demo.to_text()).unwrap();
+
+        let run = || {
+            let mut config = Config::default();
+            demo.apply(&mut config);
+            let mut emulator = Emulator::with_config(rom.clone(), config).unwrap();
+            let mut input = Input::replay(InputLog::parse(&demo.input.to_text()).unwrap());
+            for _ in 0..10 {
+                input.before_cycle(&mut emulator);
+                emulator.perform_fde_cycle().unwrap();
+            }
+            (emulator.registers()[0], emulator.registers()[1])
+        };
+        let first = run();
+        assert_eq!(first.1, 0x7);
+        assert_eq!(run(), first);
+    }
+}
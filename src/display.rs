@@ -1,24 +1,202 @@
+pub const PLANE_COUNT: usize = 2;
+
+pub const LOW_RES_WIDTH: usize = 64;
+pub const LOW_RES_HEIGHT: usize = 32;
+pub const HIGH_RES_WIDTH: usize = 128;
+pub const HIGH_RES_HEIGHT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    Low,  // 64x32
+    Tall, // 64x64, the hires CHIP-8 variant entered with 0230
+    High, // SUPER-CHIP 128x64
+}
+
+// what the screen holds before the program draws anything, for testing collisions against
+#[derive(Debug, Clone, PartialEq)]
+pub enum FillPattern {
+    On,
+    Checkerboard,        // lit where x + y is even
+    Bitmap(Vec<String>), // rows in the form to_ascii writes, `#` lit and anything else unlit
+}
+
+impl FillPattern {
+    // on, checkerboard, or the path of a text file of rows
+    pub fn load(name_or_path: &str) -> Result<FillPattern, String> {
+        match name_or_path {
+            "on" => Ok(FillPattern::On),
+            "checkerboard" => Ok(FillPattern::Checkerboard),
+            path => std::fs::read_to_string(path)
+                .map(|contents| FillPattern::Bitmap(contents.lines().map(String::from).collect()))
+                .map_err(|err| format!("Could not read fill pattern {}: {}", path, err)),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Display {
-    pub buffer: [[bool; 64]; 32],
+    pub buffer: [[u8; HIGH_RES_WIDTH]; HIGH_RES_HEIGHT], // bitmask of the planes each pixel is lit on. only the top left width x height is in use
+    pub planes: u8, // planes affected by draw and clear, selected by XO-CHIP's FN01
+    pub resolution: Resolution,
     pub draw: bool,
+    dirty: [u128; HIGH_RES_HEIGHT], // a bit per pixel that changed since take_dirty, for redrawing only those
 }
 
 impl Display {
     pub fn new() -> Self {
         Display {
-            buffer: [[false; 64]; 32],
+            buffer: [[0; HIGH_RES_WIDTH]; HIGH_RES_HEIGHT],
+            planes: 0b01,
+            resolution: Resolution::Low,
             draw: false,
+            dirty: [u128::MAX; HIGH_RES_HEIGHT],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        match self.resolution {
+            Resolution::Low | Resolution::Tall => LOW_RES_WIDTH,
+            Resolution::High => HIGH_RES_WIDTH,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self.resolution {
+            Resolution::Low => LOW_RES_HEIGHT,
+            Resolution::Tall | Resolution::High => HIGH_RES_HEIGHT,
+        }
+    }
+
+    // SUPER-CHIP's 128x64 mode, which also changes how sprites draw
+    pub fn hires(&self) -> bool {
+        self.resolution == Resolution::High
+    }
+
+    // the rows of the active resolution
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let width = self.width();
+        self.buffer[..self.height()]
+            .iter()
+            .map(move |row| &row[..width])
+    }
+
+    // whether the pixel is lit on any selected plane. anything outside the active resolution is off
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        self.in_bounds(x, y) && self.buffer[y][x] & self.planes != 0
+    }
+
+    // lights or clears the pixel on the selected planes, ignoring anything off screen
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if !self.in_bounds(x, y) {
+            return;
+        }
+        if on {
+            self.buffer[y][x] |= self.planes;
+        } else {
+            self.buffer[y][x] &= !self.planes;
+        }
+        self.dirty[y] |= 1 << x;
+        self.draw = true;
+    }
+
+    // flips the pixel on the selected planes, returning true if that turned a lit pixel off
+    pub fn xor_pixel(&mut self, x: usize, y: usize) -> bool {
+        self.xor_pixel_on(x, y, self.planes)
+    }
+
+    // as xor_pixel, but on the given planes. each plane of an XO-CHIP sprite has its own data
+    pub fn xor_pixel_on(&mut self, x: usize, y: usize, planes: u8) -> bool {
+        if !self.in_bounds(x, y) {
+            return false;
+        }
+        let collided = self.buffer[y][x] & planes != 0;
+        self.buffer[y][x] ^= planes;
+        self.dirty[y] |= 1 << x;
+        self.draw = true;
+        collided
+    }
+
+    // lights the pixel on the given planes whatever it held before, for the OR draw mode
+    pub fn or_pixel_on(&mut self, x: usize, y: usize, planes: u8) {
+        if !self.in_bounds(x, y) {
+            return;
+        }
+        self.buffer[y][x] |= planes;
+        self.dirty[y] |= 1 << x;
+        self.draw = true;
+    }
+
+    fn in_bounds(&self, x: usize, y: usize) -> bool {
+        x < self.width() && y < self.height()
+    }
+
+    // the active resolution as text, `#` for lit pixels and `.` for unlit ones, a line per row
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::new();
+        for row in self.rows() {
+            text.extend(row.iter().map(|&pixel| if pixel != 0 { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+
+    // lights pixels on the selected planes following the pattern. unlit ones are left as they are
+    pub fn fill_pattern(&mut self, pattern: &FillPattern) {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let lit = match pattern {
+                    FillPattern::On => true,
+                    FillPattern::Checkerboard => (x + y) % 2 == 0,
+                    FillPattern::Bitmap(rows) => {
+                        rows.get(y).and_then(|row| row.as_bytes().get(x)) == Some(&b'#')
+                    }
+                };
+                if lit {
+                    self.set_pixel(x, y, true);
+                }
+            }
         }
     }
 
     pub fn clear(&mut self) {
         for row in self.buffer.iter_mut() {
             for elem in row.iter_mut() {
-                *elem = false;
+                *elem &= !self.planes;
             }
         }
+        self.mark_all_dirty();
         self.draw = true;
     }
+
+    // switching resolution clears every plane
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.buffer = [[0; HIGH_RES_WIDTH]; HIGH_RES_HEIGHT];
+        self.mark_all_dirty();
+        self.draw = true;
+    }
+
+    // the pixels changed since the last call, as a bit per column in each row, and starts afresh
+    pub fn take_dirty(&mut self) -> [u128; HIGH_RES_HEIGHT] {
+        std::mem::replace(&mut self.dirty, [0; HIGH_RES_HEIGHT])
+    }
+
+    // for when the whole screen may have changed, like restoring a saved state
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = [u128::MAX; HIGH_RES_HEIGHT];
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANE_COUNT) - 1);
+    }
+
+    // the selected planes in drawing order, as single-bit masks
+    pub fn selected_planes(&self) -> impl Iterator<Item = u8> {
+        let planes = self.planes;
+        (0..PLANE_COUNT)
+            .map(|plane| 1 << plane)
+            .filter(move |mask| planes & mask != 0)
+    }
 }
 
 impl Default for Display {
@@ -26,3 +204,86 @@ impl Default for Display {
         Display::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xor_reports_collisions_and_ignores_off_screen_pixels() {
+        let mut display = Display::new();
+        assert!(!display.xor_pixel(3, 4));
+        assert!(display.get_pixel(3, 4));
+        assert!(display.xor_pixel(3, 4));
+        assert!(!display.get_pixel(3, 4));
+
+        // 64x32 is active, so the rest of the buffer is out of bounds
+        assert!(!display.xor_pixel(LOW_RES_WIDTH, 0));
+        display.set_pixel(LOW_RES_WIDTH, 0, true);
+        assert!(!display.get_pixel(LOW_RES_WIDTH, 0));
+        assert_eq!(display.buffer[0][LOW_RES_WIDTH], 0);
+    }
+
+    #[test]
+    fn dirty_tracks_changed_pixels_until_taken() {
+        let mut display = Display::new();
+        assert_eq!(display.take_dirty()[0], u128::MAX);
+        assert_eq!(display.take_dirty(), [0; HIGH_RES_HEIGHT]);
+
+        display.xor_pixel(3, 4);
+        display.set_pixel(5, 4, false);
+        display.set_pixel(LOW_RES_WIDTH, 0, true);
+        let dirty = display.take_dirty();
+        assert_eq!(dirty[4], 0b101000);
+        assert_eq!(dirty.iter().filter(|&&row| row != 0).count(), 1);
+
+        display.clear();
+        assert_eq!(display.take_dirty(), [u128::MAX; HIGH_RES_HEIGHT]);
+    }
+
+    #[test]
+    fn ascii_has_a_line_per_row_of_the_active_resolution() {
+        let mut display = Display::new();
+        display.set_pixel(1, 0, true);
+        let ascii = display.to_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+
+        assert_eq!(lines.len(), LOW_RES_HEIGHT);
+        assert_eq!(lines[0].len(), LOW_RES_WIDTH);
+        assert!(lines[0].starts_with(".#.."));
+    }
+
+    #[test]
+    fn fill_patterns_light_the_expected_pixels() {
+        let mut display = Display::new();
+        display.fill_pattern(&FillPattern::Checkerboard);
+        assert!(display.get_pixel(0, 0) && display.get_pixel(1, 1));
+        assert!(!display.get_pixel(1, 0));
+
+        let mut display = Display::new();
+        display.fill_pattern(&FillPattern::Bitmap(vec![
+            ".#".to_string(),
+            "#".to_string(),
+        ]));
+        assert_eq!(
+            display.to_ascii().lines().take(2).collect::<Vec<_>>(),
+            [
+                ".#..............................................................",
+                "#...............................................................",
+            ]
+        );
+    }
+
+    #[test]
+    fn pixels_change_only_on_the_selected_planes() {
+        let mut display = Display::new();
+        display.select_planes(0b11);
+        display.set_pixel(0, 0, true);
+        assert_eq!(display.buffer[0][0], 0b11);
+
+        display.select_planes(0b10);
+        display.set_pixel(0, 0, false);
+        assert_eq!(display.buffer[0][0], 0b01);
+        assert!(!display.get_pixel(0, 0));
+    }
+}
@@ -1,86 +1,885 @@
 use crate::{
-    controller::Controller, decoder::Instruction, decoder::ParsedInstruction, display::Display,
+    controller::Controller,
+    decoder::DecodeError,
+    decoder::Instruction,
+    decoder::ParsedInstruction,
+    decoder::INSTRUCTION_COUNT,
+    display::{Display, FillPattern, Resolution},
     font,
+    quirks::{DrawMode, Quirks, ShiftMode},
 };
-use rand::Rng;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use std::time::{Duration, Instant};
-
-struct Emulator {
-    memory: [u8; 4096],
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::fmt;
+
+pub const MEMORY_SIZE: usize = 4096;
+pub const XO_CHIP_MEMORY_SIZE: usize = 65536;
+
+pub const MAX_STACK_DEPTH: usize = 16;
+pub const PROGRAM_START: u16 = 0x200;
+// enough u64s for a bit per 16-bit opcode
+const OPCODE_SET_WORDS: usize = (u16::MAX as usize + 1) / 64;
+
+pub struct Config {
+    pub memory_size: usize,         // 4096 for CHIP-8, 65536 for XO-CHIP
+    pub max_stack_depth: usize, // the COSMAC VIP had room for 12 return addresses, later interpreters 16
+    pub decode_cache: bool, // remember decoded instructions by address instead of parsing every fetch
+    pub load_address: u16, // where the program is copied and execution starts. 0x600 for ETI-660 ROMs
+    pub font_address: u16, // where the hex digit sprites FX29 points at are stored
+    pub seed: Option<u64>, // fixes the CXNN random numbers so a run replays identically
+    pub ignore_machine_calls: bool, // treat 0NNN as a no-op rather than stopping with an error
+    pub halt_on_odd_pc: bool, // stop with an error on a misaligned fetch instead of warning and carrying on
+    pub warn_low_writes: bool, // warn when FX33 or FX55 writes below 0x200, into the font and interpreter area
+    pub strict: bool, // warn when an instruction reads a register nothing has set since reset
+    pub profile: bool, // count how often each instruction runs
+    pub quirks: Quirks,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            memory_size: MEMORY_SIZE,
+            max_stack_depth: MAX_STACK_DEPTH,
+            decode_cache: false,
+            load_address: PROGRAM_START,
+            font_address: font::FONT_OFFSET as u16,
+            seed: None,
+            ignore_machine_calls: false,
+            halt_on_odd_pc: false,
+            warn_low_writes: false,
+            strict: false,
+            profile: false,
+            quirks: Quirks::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmulatorError {
+    InvalidInstruction {
+        address: u16,
+        source: DecodeError,
+    },
+    ProgramDoesNotFit {
+        load_address: u16,
+        size: usize,
+        memory_size: usize,
+    },
+    FontDoesNotFit {
+        font_address: u16,
+        memory_size: usize,
+    },
+    FontOverlapsProgram {
+        font_address: u16,
+        load_address: u16,
+        size: usize,
+    },
+    StackOverflow {
+        address: u16,
+        depth: usize,
+    },
+    StackUnderflow {
+        address: u16,
+    },
+    MachineCall {
+        address: u16,
+        target: u16,
+    },
+    MisalignedProgramCounter {
+        address: u16,
+    },
+    ProgramCounterOutOfBounds {
+        address: u16,
+    },
+    MemoryOutOfBounds {
+        address: u16,
+        index_register: u16,
+        length: usize, // bytes the instruction reads or writes from I onwards
+    },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::InvalidInstruction { address, source } => {
+                write!(f, "{} at {:#05x}", source, address)
+            }
+            EmulatorError::ProgramDoesNotFit {
+                load_address,
+                size,
+                memory_size,
+            } => write!(
+                f,
+                "A {} byte program loaded at {:#05x} does not fit in {} bytes of memory",
+                size, load_address, memory_size
+            ),
+            EmulatorError::FontDoesNotFit {
+                font_address,
+                memory_size,
+            } => write!(
+                f,
+                "The font at {:#05x} does not fit in {} bytes of memory",
+                font_address, memory_size
+            ),
+            EmulatorError::FontOverlapsProgram {
+                font_address,
+                load_address,
+                size,
+            } => write!(
+                f,
+                "The font at {:#05x} overlaps the {} byte program loaded at {:#05x}",
+                font_address, size, load_address
+            ),
+            EmulatorError::StackOverflow { address, depth } => write!(
+                f,
+                "Stack overflow: call at {:#05x} exceeds the maximum depth of {}",
+                address, depth
+            ),
+            EmulatorError::StackUnderflow { address } => write!(
+                f,
+                "Stack underflow: return at {:#05x} with no subroutine to return from",
+                address
+            ),
+            EmulatorError::MachineCall { address, target } => write!(
+                f,
+                "Machine code call to {:#05x} at {:#05x} is not supported",
+                target, address
+            ),
+            EmulatorError::MemoryOutOfBounds {
+                address,
+                index_register,
+                length,
+            } => write!(
+                f,
+                "Instruction at {:#05x} accesses {} bytes from I = {:#05x}, past the end of memory",
+                address, length, index_register
+            ),
+            EmulatorError::MisalignedProgramCounter { address } => write!(
+                f,
+                "Program counter {:#05x} is odd, so instructions would be fetched misaligned",
+                address
+            ),
+            EmulatorError::ProgramCounterOutOfBounds { address } => write!(
+                f,
+                "Program counter {:#05x} runs past the end of memory",
+                address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn unseeded_rng() -> StdRng {
+    StdRng::from_entropy()
+}
+
+// there's no entropy source in the browser build, and the page always passes a seed anyway
+#[cfg(target_arch = "wasm32")]
+fn unseeded_rng() -> StdRng {
+    StdRng::seed_from_u64(0)
+}
+
+// something suspicious a ROM did that isn't an error, passed to the on_warning callback
+#[derive(Debug, PartialEq)]
+pub enum Warning {
+    MisalignedProgramCounter {
+        address: u16,
+    },
+    LowWrite {
+        address: u16, // the instruction doing the write
+        index_register: u16,
+    },
+    UninitializedRead {
+        address: u16,
+        registers: u16, // a bit for each register read before anything set it
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::MisalignedProgramCounter { address } => write!(
+                f,
+                "program counter {:#05x} is odd, fetching misaligned instructions",
+                address
+            ),
+            Warning::LowWrite {
+                address,
+                index_register,
+            } => write!(
+                f,
+                "instruction at {:#05x} writes to {:#05x}, below the program area",
+                address, index_register
+            ),
+            Warning::UninitializedRead { address, registers } => {
+                let names: Vec<String> = (0..16)
+                    .filter(|register| registers & (1 << register) != 0)
+                    .map(|register| format!("V{:X}", register))
+                    .collect();
+                write!(
+                    f,
+                    "instruction at {:#05x} reads {} before anything set it",
+                    address,
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+// reported by perform_fde_cycle when the frontend should pause for debugging
+#[derive(Debug, PartialEq)]
+pub enum Break {
+    Watch {
+        address: u16,
+        old: u8,
+        new: u8,
+        program_counter: u16, // the instruction that made the change
+    },
+    Breakpoint {
+        address: u16,
+    },
+    SteppedOver {
+        address: u16, // where the stepped over call returned to
+    },
+}
+
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Break::Watch {
+                address,
+                old,
+                new,
+                program_counter,
+            } => write!(
+                f,
+                "Watch {:#05x} changed from {:#04x} to {:#04x} by the instruction at {:#05x}",
+                address, old, new, program_counter
+            ),
+            Break::Breakpoint { address } => write!(f, "Breakpoint at {:#05x}", address),
+            Break::SteppedOver { address } => {
+                write!(f, "Stepped over the call, returned to {:#05x}", address)
+            }
+        }
+    }
+}
+
+// everything the program can see, captured so the machine can be put back to this point.
+// keys, breakpoints and watches belong to the session rather than the machine and are left out
+#[derive(Clone)]
+pub struct EmulatorState {
+    memory: Vec<u8>,
     display: Display,
-    program_counter: u16, // most games require only u12, but u16 is used
-    index_register: u16,  // most games require only u12, but u16 is used
+    program_counter: u16,
+    index_register: u16,
     stack: Vec<u16>,
     delay_timer: u8,
     sound_timer: u8,
     registers: [u8; 16],
-    controller: Controller,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    rng: StdRng,
+    cycles: u64,
+    initialized_registers: Option<u16>,
+}
+
+pub struct Emulator {
+    pub(crate) memory: Vec<u8>,
+    pub(crate) display: Display,
+    load_address: u16,
+    font_address: u16,
+    pub(crate) program_counter: u16, // most games require only u12, but u16 is used
+    pub(crate) index_register: u16,  // most games require only u12, but u16 is used
+    pub(crate) stack: Vec<u16>,
+    pub(crate) max_stack_depth: usize,
+    ignore_machine_calls: bool,
+    halt_on_odd_pc: bool,
+    misaligned: bool, // already warned about the current run of odd addresses
+    low_writes_warned: Option<HashSet<u16>>, // instructions already warned about, None unless warn_low_writes
+    initialized_registers: Option<u16>, // a bit per register written since reset, None unless strict
+    uninitialized_warned: HashSet<u16>, // instructions already warned about reading one that wasn't
+    pub(crate) quirks: Quirks,
+    decode_cache: Vec<Option<ParsedInstruction>>, // indexed by address, empty when the cache is disabled
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) registers: [u8; 16],
+    pub(crate) controller: Controller,
+    pub(crate) audio_pattern: Option<[u8; 16]>, // XO-CHIP sample pattern, played instead of the beep once loaded
+    pub(crate) pitch: u8,
+    rng: StdRng,
+    cycles: u64,                               // instructions executed so far
+    profile: Option<[u64; INSTRUCTION_COUNT]>, // runs of each instruction by discriminant, None unless profiling
+    watches: Vec<(u16, u8)>,                   // watched addresses with the value last seen there
+    breakpoints: HashSet<u16>,
+    stopped_at: Option<u16>, // breakpoint just reported, so resuming runs its instruction instead of stopping again
+    pending_break: Option<Break>,
+    step_over_depth: Option<usize>, // stack depth to pause at once the stepped over call returns
+    last_instruction: Option<ParsedInstruction>, // the most recently decoded, for debugging overlays
+    opcodes_seen: Vec<u64>, // a bit for each of the 65536 raw opcodes that has run, for the run summary
+    invalid_opcodes: u64,   // fetches that failed to decode
+    collision_callback: Box<dyn FnMut()>, // called whenever a draw collides with lit pixels, for frontends to flash or buzz
+    warning_callback: Box<dyn FnMut(Warning)>, // ignores warnings unless a frontend asks for them
+}
+
+// fresh memory holding the font and the program, which mustn't overwrite each other
+fn load_memory(
+    program: &[u8],
+    memory_size: usize,
+    load_address: u16,
+    font_address: u16,
+) -> Result<Vec<u8>, EmulatorError> {
+    let start = load_address as usize;
+    if start + program.len() > memory_size {
+        return Err(EmulatorError::ProgramDoesNotFit {
+            load_address,
+            size: program.len(),
+            memory_size,
+        });
+    }
+    let font = font_address as usize..font_address as usize + font::FONT.len();
+    if font.end > memory_size {
+        return Err(EmulatorError::FontDoesNotFit {
+            font_address,
+            memory_size,
+        });
+    }
+    if font.start < start + program.len() && start < font.end {
+        return Err(EmulatorError::FontOverlapsProgram {
+            font_address,
+            load_address,
+            size: program.len(),
+        });
+    }
+
+    let mut memory = vec![0; memory_size];
+    memory[font].copy_from_slice(&font::FONT);
+    memory[start..start + program.len()].copy_from_slice(program);
+    Ok(memory)
 }
 
 impl Emulator {
-    fn new(program: Vec<u8>) -> Self {
-        let mut memory = [0; 4096];
+    // panics if the program is larger than the 3584 bytes above 0x200. use with_config to handle that
+    pub fn new(program: Vec<u8>) -> Self {
+        Self::with_config(program, Config::default()).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        memory[font::FONT_OFFSET..font::FONT_OFFSET + font::FONT.len()]
-            .copy_from_slice(&font::FONT);
-        memory[512..512 + program.len()].copy_from_slice(&program);
+    pub fn with_config(program: Vec<u8>, config: Config) -> Result<Self, EmulatorError> {
+        let memory = load_memory(
+            &program,
+            config.memory_size,
+            config.load_address,
+            config.font_address,
+        )?;
 
-        Self {
+        Ok(Self {
             memory,
             display: Display::new(),
-            program_counter: 512,
+            load_address: config.load_address,
+            font_address: config.font_address,
+            program_counter: config.load_address,
             index_register: 0,
             stack: Vec::new(),
+            max_stack_depth: config.max_stack_depth,
+            ignore_machine_calls: config.ignore_machine_calls,
+            halt_on_odd_pc: config.halt_on_odd_pc,
+            misaligned: false,
+            low_writes_warned: config.warn_low_writes.then(HashSet::new),
+            initialized_registers: config.strict.then_some(0),
+            uninitialized_warned: HashSet::new(),
+            quirks: config.quirks,
+            decode_cache: if config.decode_cache {
+                vec![None; config.memory_size]
+            } else {
+                Vec::new()
+            },
             delay_timer: 0,
             sound_timer: 0,
             registers: [0; 16],
             controller: Controller::new(),
+            audio_pattern: None,
+            pitch: 64,
+            cycles: 0,
+            profile: config.profile.then_some([0; INSTRUCTION_COUNT]),
+            rng: match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => unseeded_rng(),
+            },
+            watches: Vec::new(),
+            breakpoints: HashSet::new(),
+            stopped_at: None,
+            pending_break: None,
+            step_over_depth: None,
+            last_instruction: None,
+            opcodes_seen: vec![0; OPCODE_SET_WORDS],
+            invalid_opcodes: 0,
+            collision_callback: Box::new(|| {}),
+            warning_callback: Box::new(|_| {}),
+        })
+    }
+
+    // preconditions for tests that exercise one instruction without a setup ROM
+    pub fn with_registers(mut self, registers: [u8; 16]) -> Self {
+        self.registers = registers;
+        self
+    }
+
+    pub fn with_index_register(mut self, index_register: u16) -> Self {
+        self.index_register = index_register;
+        self
+    }
+
+    // starts the screen with the pattern already drawn, so collisions can be tested from the first sprite
+    pub fn with_fill(mut self, pattern: &FillPattern) -> Self {
+        self.display.fill_pattern(pattern);
+        self
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn display_mut(&mut self) -> &mut Display {
+        &mut self.display
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    // the buzzer sounds for as long as the sound timer is above zero
+    pub fn beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // how many times each instruction has run, indexed as Instruction::ALL, when profiling is on
+    pub fn profile(&self) -> Option<&[u64; INSTRUCTION_COUNT]> {
+        self.profile.as_ref()
+    }
+
+    pub fn controller(&self) -> &Controller {
+        &self.controller
+    }
+
+    pub fn controller_mut(&mut self) -> &mut Controller {
+        &mut self.controller
+    }
+
+    // how many different raw opcodes have run since reset
+    pub fn unique_opcodes(&self) -> u32 {
+        self.opcodes_seen.iter().map(|word| word.count_ones()).sum()
+    }
+
+    // how many fetches failed to decode since reset. as that stops the machine it's at most one
+    // unless the frontend carries on past errors
+    pub fn invalid_opcodes(&self) -> u64 {
+        self.invalid_opcodes
+    }
+
+    // the instruction the last cycle ran, or tried to run if it stopped with an error
+    pub fn last_instruction(&self) -> Option<ParsedInstruction> {
+        self.last_instruction
+    }
+
+    // parked on FX0A, so frontends can show that the ROM is waiting for input rather than frozen
+    pub fn waiting_for_key(&self) -> bool {
+        self.controller.is_waiting()
+    }
+
+    // one 60Hz tick. frontends call this on their own clock, tests whenever they like
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    pub fn on_collision(&mut self, callback: impl FnMut() + 'static) {
+        self.collision_callback = Box::new(callback);
+    }
+
+    pub fn on_warning(&mut self, callback: impl FnMut(Warning) + 'static) {
+        self.warning_callback = Box::new(callback);
+    }
+
+    // pause when the byte at this address changes
+    pub fn watch(&mut self, address: u16) {
+        let value = self.memory.get(address as usize).copied().unwrap_or(0);
+        self.watches.push((address, value));
+    }
+
+    // pause before executing the instruction at this address
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn has_break(&self) -> bool {
+        self.pending_break.is_some()
+    }
+
+    // when the next instruction is a 2NNN call, arranges for a break once it returns and gives true,
+    // so the frontend runs on instead of stepping into it. otherwise a plain step is all that's needed
+    pub fn step_over(&mut self) -> bool {
+        let address = self.program_counter as usize;
+        let is_call = self.memory.get(address..address + 2).is_some_and(|bytes| {
+            ParsedInstruction::parse(u16::from_be_bytes([bytes[0], bytes[1]]))
+                .is_ok_and(|parsed| parsed.instruction == Instruction::PushStackSetProgramCounter)
+        });
+        if is_call {
+            self.step_over_depth = Some(self.stack.len());
         }
+        is_call
     }
 
-    fn perform_fde_cycle(&mut self) {
-        // Fetch
-        let instruction_msb =
-            (*self.memory.get(self.program_counter as usize).unwrap() as u16) << 8;
-        let instruction_lsb = *self.memory.get(self.program_counter as usize + 1).unwrap() as u16;
-        let raw_instruction = instruction_msb | instruction_lsb;
+    // the reason the last cycle asked to pause, if any
+    pub fn take_break(&mut self) -> Option<Break> {
+        self.pending_break.take()
+    }
 
-        // Increment program counter
-        self.program_counter += 2;
+    // registers, timers and the stack, for printing when paused
+    pub fn dump_state(&self) -> String {
+        let mut state = format!(
+            "cycle {}  PC {:#05x}  I {:#05x}  DT {:02x}  ST {:02x}  stack {:03x?}\n",
+            self.cycles,
+            self.program_counter,
+            self.index_register,
+            self.delay_timer,
+            self.sound_timer,
+            self.stack
+        );
+        for (half, values) in self.registers.chunks(8).enumerate() {
+            let line: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X} {:02x}", half * 8 + i, value))
+                .collect();
+            state.push_str(&line.join("  "));
+            state.push('\n');
+        }
+        state
+    }
 
-        // Decode & Execute
-        let instruction = ParsedInstruction::parse(raw_instruction);
-        self.execute_instruction(instruction);
+    // starts a new program from scratch with the same settings, quirks and debugger state.
+    // on error the machine is left as it was
+    pub fn reset(&mut self, program: Vec<u8>) -> Result<(), EmulatorError> {
+        self.memory = load_memory(
+            &program,
+            self.memory.len(),
+            self.load_address,
+            self.font_address,
+        )?;
+        self.display = Display::new();
+        self.display.draw = true;
+        self.program_counter = self.load_address;
+        self.index_register = 0;
+        self.stack.clear();
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.registers = [0; 16];
+        self.audio_pattern = None;
+        self.pitch = 64;
+        self.cycles = 0;
+        self.decode_cache.fill(None);
+        self.restore_watches();
+        self.stopped_at = None;
+        self.pending_break = None;
+        self.step_over_depth = None;
+        self.last_instruction = None;
+        self.opcodes_seen.fill(0);
+        self.invalid_opcodes = 0;
+        if let Some(initialized) = self.initialized_registers.as_mut() {
+            *initialized = 0;
+        }
+        Ok(())
     }
 
-    fn execute_instruction(&mut self, parsed_instruction: ParsedInstruction) {
+    pub fn save_state(&self) -> EmulatorState {
+        EmulatorState {
+            memory: self.memory.clone(),
+            display: self.display.clone(),
+            program_counter: self.program_counter,
+            index_register: self.index_register,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            registers: self.registers,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: self.rng.clone(),
+            cycles: self.cycles,
+            initialized_registers: self.initialized_registers,
+        }
+    }
+
+    pub fn restore_state(&mut self, state: EmulatorState) {
+        self.memory = state.memory;
+        self.display = state.display;
+        self.display.mark_all_dirty();
+        self.display.draw = true;
+        self.program_counter = state.program_counter;
+        self.index_register = state.index_register;
+        self.stack = state.stack;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.registers = state.registers;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.rng = state.rng;
+        self.cycles = state.cycles;
+        if self.initialized_registers.is_some() {
+            // a state saved without strict didn't track them, so every register counts as set
+            self.initialized_registers = Some(state.initialized_registers.unwrap_or(u16::MAX));
+        }
+
+        // memory may differ from what was cached or last seen by a watch
+        self.decode_cache.fill(None);
+        self.restore_watches();
+        self.stopped_at = None;
+        self.step_over_depth = None;
+        self.last_instruction = None;
+    }
+
+    fn restore_watches(&mut self) {
+        for (address, last) in self.watches.iter_mut() {
+            if let Some(&value) = self.memory.get(*address as usize) {
+                *last = value;
+            }
+        }
+    }
+
+    pub fn perform_fde_cycle(&mut self) -> Result<(), EmulatorError> {
+        let program_counter = self.program_counter;
+
+        if self.breakpoints.contains(&program_counter) && self.stopped_at != Some(program_counter) {
+            self.stopped_at = Some(program_counter);
+            self.pending_break.get_or_insert(Break::Breakpoint {
+                address: program_counter,
+            });
+            return Ok(());
+        }
+        self.stopped_at = None;
+
+        // instructions are two bytes on even addresses, so an odd one means a bad jump or skip
+        if program_counter % 2 == 1 {
+            if self.halt_on_odd_pc {
+                return Err(EmulatorError::MisalignedProgramCounter {
+                    address: program_counter,
+                });
+            }
+            if !self.misaligned {
+                (self.warning_callback)(Warning::MisalignedProgramCounter {
+                    address: program_counter,
+                });
+                self.misaligned = true;
+            }
+        } else {
+            self.misaligned = false;
+        }
+        self.cycles += 1;
+
+        let result = self.fetch_decode_execute();
+        if !self.watches.is_empty() {
+            self.check_watches(program_counter);
+        }
+        if self
+            .step_over_depth
+            .is_some_and(|depth| self.stack.len() <= depth)
+        {
+            self.step_over_depth = None;
+            self.pending_break.get_or_insert(Break::SteppedOver {
+                address: self.program_counter,
+            });
+        }
+        result
+    }
+
+    fn check_watches(&mut self, program_counter: u16) {
+        for (address, last) in self.watches.iter_mut() {
+            let Some(&value) = self.memory.get(*address as usize) else {
+                continue;
+            };
+            if value != *last {
+                // keep the first change if several watches trip in one cycle
+                self.pending_break.get_or_insert(Break::Watch {
+                    address: *address,
+                    old: *last,
+                    new: value,
+                    program_counter,
+                });
+                *last = value;
+            }
+        }
+    }
+
+    fn fetch_decode_execute(&mut self) -> Result<(), EmulatorError> {
+        let address = self.program_counter as usize;
+
+        let instruction = match self.decode_cache.get(address) {
+            Some(&Some(cached)) => cached,
+            _ => {
+                let raw_instruction = self.fetch()?;
+
+                // Decode
+                let instruction = match ParsedInstruction::parse(raw_instruction) {
+                    Ok(instruction) => instruction,
+                    Err(source) => {
+                        self.invalid_opcodes += 1;
+                        return Err(EmulatorError::InvalidInstruction {
+                            address: self.program_counter,
+                            source,
+                        });
+                    }
+                };
+                if let Some(entry) = self.decode_cache.get_mut(address) {
+                    *entry = Some(instruction);
+                }
+                instruction
+            }
+        };
+
+        self.last_instruction = Some(instruction);
+        let raw_instruction = instruction.raw_instruction as usize;
+        self.opcodes_seen[raw_instruction / 64] |= 1 << (raw_instruction % 64);
+
+        // Increment program counter, wrapping past 0xFFFE in 64 KiB of memory
+        self.program_counter = self.program_counter.wrapping_add(2);
+
+        // Execute
+        self.execute_instruction(instruction)
+    }
+
+    // the word at the program counter. CHIP-8 is big-endian, so the byte at the lower address is
+    // the high byte: 0x12 then 0x34 is 0x1234. the word has to lie wholly inside memory
+    fn fetch(&self) -> Result<u16, EmulatorError> {
+        let address = self.program_counter as usize;
+        match self.memory.get(address..address + 2) {
+            Some(&[high, low]) => Ok(u16::from_be_bytes([high, low])),
+            _ => Err(EmulatorError::ProgramCounterOutOfBounds {
+                address: self.program_counter,
+            }),
+        }
+    }
+
+    // all stores go through here so self-modifying code never runs a stale cached decode
+    pub(crate) fn write_memory(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+
+        if !self.decode_cache.is_empty() {
+            // the byte is the low half of an instruction starting one address earlier, or the high half of its own
+            if let Some(entry) = address
+                .checked_sub(1)
+                .and_then(|a| self.decode_cache.get_mut(a))
+            {
+                *entry = None;
+            }
+            if let Some(entry) = self.decode_cache.get_mut(address) {
+                *entry = None;
+            }
+        }
+    }
+
+    pub(crate) fn execute_instruction(
+        &mut self,
+        parsed_instruction: ParsedInstruction,
+    ) -> Result<(), EmulatorError> {
+        if let Some(profile) = &mut self.profile {
+            profile[parsed_instruction.instruction as usize] += 1;
+        }
+        if let Some(initialized) = self.initialized_registers {
+            let (reads, writes) = self.register_access(&parsed_instruction);
+            self.warn_uninitialized_reads(reads & !initialized);
+            self.initialized_registers = Some(initialized | writes);
+        }
+
         match parsed_instruction.instruction {
+            // original interpreters ran these as native subroutines. later ones skip them, which some
+            // old ROMs rely on, so ignoring them is opt-in
+            Instruction::MachineCall => {
+                if !self.ignore_machine_calls {
+                    return Err(EmulatorError::MachineCall {
+                        address: self.program_counter.wrapping_sub(2),
+                        target: parsed_instruction.nnn,
+                    });
+                }
+            }
             Instruction::Clear => self.display.clear(),
+            Instruction::LowResolution => self.display.set_resolution(Resolution::Low),
+            Instruction::TallResolution => self.display.set_resolution(Resolution::Tall),
+            Instruction::HighResolution => self.display.set_resolution(Resolution::High),
             Instruction::PopStack => {
-                self.program_counter = self.stack.pop().expect("No value to pop off the stack")
+                self.program_counter = self.stack.pop().ok_or(EmulatorError::StackUnderflow {
+                    address: self.program_counter.wrapping_sub(2),
+                })?
             }
             Instruction::SetProgramCounter => self.program_counter = parsed_instruction.nnn,
             Instruction::PushStackSetProgramCounter => {
+                if self.stack.len() >= self.max_stack_depth {
+                    return Err(EmulatorError::StackOverflow {
+                        address: self.program_counter.wrapping_sub(2),
+                        depth: self.max_stack_depth,
+                    });
+                }
                 self.stack.push(self.program_counter);
                 self.program_counter = parsed_instruction.nnn;
             }
             Instruction::SkipIfEqualImmediate => {
                 if self.registers[parsed_instruction.x] == parsed_instruction.nn {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             Instruction::SkipIfNotEqualImmediate => {
                 if self.registers[parsed_instruction.x] != parsed_instruction.nn {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             Instruction::SkipIfEqualRegister => {
                 if self.registers[parsed_instruction.x] == self.registers[parsed_instruction.y] {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             Instruction::SetRegister => {
@@ -94,19 +893,22 @@ impl Emulator {
                 self.registers[parsed_instruction.x] = self.registers[parsed_instruction.y]
             }
             Instruction::LogicalOr => {
-                self.registers[parsed_instruction.x] =
-                    self.registers[parsed_instruction.x] | self.registers[parsed_instruction.y];
-                self.registers[0xF] = 0;
+                self.registers[parsed_instruction.x] |= self.registers[parsed_instruction.y];
+                if !self.quirks.keep_vf {
+                    self.registers[0xF] = 0;
+                }
             }
             Instruction::LogicalAnd => {
-                self.registers[parsed_instruction.x] =
-                    self.registers[parsed_instruction.x] & self.registers[parsed_instruction.y];
-                self.registers[0xF] = 0;
+                self.registers[parsed_instruction.x] &= self.registers[parsed_instruction.y];
+                if !self.quirks.keep_vf {
+                    self.registers[0xF] = 0;
+                }
             }
             Instruction::LogicalXor => {
-                self.registers[parsed_instruction.x] =
-                    self.registers[parsed_instruction.x] ^ self.registers[parsed_instruction.y];
-                self.registers[0xF] = 0;
+                self.registers[parsed_instruction.x] ^= self.registers[parsed_instruction.y];
+                if !self.quirks.keep_vf {
+                    self.registers[0xF] = 0;
+                }
             }
             Instruction::Addition => {
                 let (result, overflow) = self.registers[parsed_instruction.x]
@@ -139,240 +941,1416 @@ impl Emulator {
                 }
             }
             Instruction::LeftShift => {
-                let (result, overflow) = (
-                    self.registers[parsed_instruction.y] << 1,
-                    self.registers[parsed_instruction.y] & (1 << 7),
-                );
+                let source = self.shift_source(&parsed_instruction);
+                let (result, overflow) = (source << 1, source & (1 << 7));
                 self.registers[parsed_instruction.x] = result;
                 self.registers[0xF] = overflow >> 7;
             }
             Instruction::RightShift => {
-                let (result, overflow) = (
-                    self.registers[parsed_instruction.y] >> 1,
-                    self.registers[parsed_instruction.y] & 1,
-                );
+                let source = self.shift_source(&parsed_instruction);
+                let (result, overflow) = (source >> 1, source & 1);
                 self.registers[parsed_instruction.x] = result;
                 self.registers[0xF] = overflow;
             }
             Instruction::SkipIfNotEqualRegister => {
                 if self.registers[parsed_instruction.x] != self.registers[parsed_instruction.y] {
-                    self.program_counter += 2;
+                    self.skip();
                 }
             }
             Instruction::SetIndexRegister => self.index_register = parsed_instruction.nnn,
+            Instruction::SetIndexRegisterLong => {
+                // the 16-bit address is stored in the word following the instruction
+                self.index_register = self.fetch()?;
+                self.program_counter = self.program_counter.wrapping_add(2);
+            }
             Instruction::SetProgramCounterOffset => {
-                self.program_counter = parsed_instruction.nnn + self.registers[0x0] as u16
+                // NNN + V0 can pass the end of memory, so the jump wraps around to the start
+                let offset_register = if self.quirks.jump_uses_vx {
+                    parsed_instruction.x
+                } else {
+                    0x0
+                };
+                let target =
+                    parsed_instruction.nnn as usize + self.registers[offset_register] as usize;
+                self.program_counter = (target % self.memory.len()) as u16;
             }
             Instruction::RandomNumber => {
-                self.registers[parsed_instruction.x] =
-                    rand::thread_rng().gen::<u8>() & parsed_instruction.nn
+                self.registers[parsed_instruction.x] = self.rng.gen::<u8>() & parsed_instruction.nn
             }
-            Instruction::Draw => self.execute_draw_instruction(&parsed_instruction),
+            Instruction::Draw => self.execute_draw_instruction(&parsed_instruction)?,
+            Instruction::SelectPlane => self.display.select_planes(parsed_instruction.x as u8),
             Instruction::KeyDown => {
                 if self
                     .controller
-                    .is_key_pressed(self.registers[parsed_instruction.x])
+                    .is_key_pressed(self.key_in(parsed_instruction.x))
                 {
-                    self.program_counter += 2
+                    self.skip();
                 }
             }
             Instruction::KeyNotDown => {
                 if !self
                     .controller
-                    .is_key_pressed(self.registers[parsed_instruction.x])
+                    .is_key_pressed(self.key_in(parsed_instruction.x))
                 {
-                    self.program_counter += 2
+                    self.skip();
                 }
             }
             Instruction::CopyDelayTimer => self.registers[parsed_instruction.x] = self.delay_timer,
             Instruction::SetDelayTimer => self.delay_timer = self.registers[parsed_instruction.x],
             Instruction::SetSoundTimer => self.sound_timer = self.registers[parsed_instruction.x],
+            Instruction::LoadAudioPattern => {
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[self.index_range(16)?]);
+                self.audio_pattern = Some(pattern);
+            }
+            Instruction::SetPitch => self.pitch = self.registers[parsed_instruction.x],
             Instruction::AddToIndexRegister => {
-                let (result, overflow) = self
+                let result = self
                     .index_register
-                    .overflowing_add(self.registers[parsed_instruction.x].into());
-                if overflow || result > 0x0FFF {
-                    self.registers[0xF] = 1;
+                    .wrapping_add(self.registers[parsed_instruction.x].into());
+                if self.memory.len() > MEMORY_SIZE {
+                    // XO-CHIP's I is a full 16 bits and FX1E leaves VF alone
+                    self.index_register = result;
+                } else {
+                    // the Amiga interpreter's overflow flag, which some games rely on
+                    if result > 0x0FFF {
+                        self.registers[0xF] = 1;
+                    }
+                    self.index_register = result & 0x0FFF;
                 }
-
-                self.index_register = result % 0x0FFF;
             }
             Instruction::WaitForKeyPress => {
-                if let Some(key) = self.controller.last_pressed {
+                if let Some(key) = self.controller.wait_for_key_release() {
                     self.registers[parsed_instruction.x] = key;
                 } else {
-                    self.program_counter -= 2;
+                    self.program_counter = self.program_counter.wrapping_sub(2);
                 }
             }
             Instruction::SetIndexRegisterToFontCharacter => {
-                self.index_register = (font::FONT_OFFSET as u8
-                    + (self.registers[parsed_instruction.x] & 0x0F))
-                    .into();
+                let digit = (self.registers[parsed_instruction.x] & 0x0F) as u16;
+                self.index_register = self.font_address + digit * font::CHARACTER_HEIGHT;
             }
             Instruction::ConvertToDecimal => {
+                self.check_low_write();
+                let start = self.index_range(3)?.start;
                 let mut x_register = self.registers[parsed_instruction.x];
                 for i in (0..=2).rev() {
-                    self.memory[(self.index_register + i) as usize] = x_register % 10;
+                    self.write_memory(start + i, x_register % 10);
                     x_register /= 10;
                 }
             }
             Instruction::WriteToMemory => {
+                self.check_low_write();
+                let start = self.index_range(parsed_instruction.x + 1)?.start;
                 for i in 0..=parsed_instruction.x {
-                    self.memory[(self.index_register + i as u16) as usize] = self.registers[i];
+                    self.write_memory(start + i, self.registers[i]);
+                }
+                if !self.quirks.keep_index {
+                    self.index_register = self
+                        .index_register
+                        .wrapping_add(1 + parsed_instruction.x as u16);
                 }
-                self.index_register += 1 + parsed_instruction.x as u16;
             }
             Instruction::ReadFromMemory => {
-                for i in 0..=parsed_instruction.x {
-                    self.registers[i] = self.memory[(self.index_register + i as u16) as usize];
+                let range = self.index_range(parsed_instruction.x + 1)?;
+                self.registers[..=parsed_instruction.x].copy_from_slice(&self.memory[range]);
+                if !self.quirks.keep_index {
+                    self.index_register = self
+                        .index_register
+                        .wrapping_add(1 + parsed_instruction.x as u16);
                 }
-                self.index_register += 1 + parsed_instruction.x as u16;
             }
         }
+
+        Ok(())
     }
 
-    fn execute_draw_instruction(&mut self, parsed_instruction: &ParsedInstruction) {
-        let x_pos = self.registers[parsed_instruction.x] % 64;
-        let y_pos = self.registers[parsed_instruction.y] % 32;
+    // the memory at I that an instruction is about to read or write, or an error if it runs off the end
+    fn index_range(&self, length: usize) -> Result<std::ops::Range<usize>, EmulatorError> {
+        let start = self.index_register as usize;
+        if start + length > self.memory.len() {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                address: self.program_counter.wrapping_sub(2),
+                index_register: self.index_register,
+                length,
+            });
+        }
+        Ok(start..start + length)
+    }
 
+    // the key EX9E and EXA1 test. the COSMAC VIP only latched the low nibble of VX onto the keypad,
+    // so 0x2F tests key F just as 0x0F does
+    fn key_in(&self, register: usize) -> u8 {
+        self.registers[register] & 0x0F
+    }
+
+    // the bytes a draw reads from I. with the wrap_index quirk they may run off the end of memory
+    // and continue from the start, otherwise that's an error like any other access past the end
+    fn sprite_bytes(&self, length: usize) -> Result<Vec<u8>, EmulatorError> {
+        if !self.quirks.wrap_index {
+            return Ok(self.memory[self.index_range(length)?].to_vec());
+        }
         let start = self.index_register as usize;
-        let end = start + parsed_instruction.n as usize;
-        let bytes = if let Some(slice) = self.memory.get(start..end) {
-            slice.to_vec()
+        Ok((start..start + length)
+            .map(|address| self.memory[address % self.memory.len()])
+            .collect())
+    }
+
+    // once for each instruction, which has already advanced the program counter past itself
+    fn check_low_write(&mut self) {
+        if self.index_register >= PROGRAM_START {
+            return;
+        }
+        let address = self.program_counter.wrapping_sub(2);
+        if let Some(warned) = self.low_writes_warned.as_mut() {
+            if warned.insert(address) {
+                (self.warning_callback)(Warning::LowWrite {
+                    address,
+                    index_register: self.index_register,
+                });
+            }
+        }
+    }
+
+    // the registers an instruction reads and writes, a bit each
+    fn register_access(&self, parsed_instruction: &ParsedInstruction) -> (u16, u16) {
+        let x = 1 << parsed_instruction.x;
+        let y = 1 << parsed_instruction.y;
+        let flag = 1 << 0xF;
+        let up_to_x = ((1u32 << (parsed_instruction.x + 1)) - 1) as u16;
+        match parsed_instruction.instruction {
+            Instruction::SkipIfEqualImmediate
+            | Instruction::SkipIfNotEqualImmediate
+            | Instruction::KeyDown
+            | Instruction::KeyNotDown
+            | Instruction::SetDelayTimer
+            | Instruction::SetSoundTimer
+            | Instruction::AddToIndexRegister
+            | Instruction::SetIndexRegisterToFontCharacter
+            | Instruction::ConvertToDecimal
+            | Instruction::SetPitch => (x, 0),
+            Instruction::SkipIfEqualRegister | Instruction::SkipIfNotEqualRegister => (x | y, 0),
+            Instruction::SetRegister
+            | Instruction::RandomNumber
+            | Instruction::CopyDelayTimer
+            | Instruction::WaitForKeyPress => (0, x),
+            Instruction::AddToRegister => (x, x),
+            Instruction::CopyFromRegisterToRegister => (y, x),
+            Instruction::LogicalOr | Instruction::LogicalAnd | Instruction::LogicalXor => {
+                if self.quirks.keep_vf {
+                    (x | y, x)
+                } else {
+                    (x | y, x | flag)
+                }
+            }
+            Instruction::Addition | Instruction::Subtraction | Instruction::FlippedSubtraction => {
+                (x | y, x | flag)
+            }
+            Instruction::RightShift | Instruction::LeftShift => match self.quirks.shift {
+                ShiftMode::VyIntoVx => (y, x | flag),
+                ShiftMode::InPlace => (x, x | flag),
+            },
+            Instruction::SetProgramCounterOffset if self.quirks.jump_uses_vx => (x, 0),
+            Instruction::SetProgramCounterOffset => (1, 0),
+            Instruction::Draw => (x | y, flag),
+            Instruction::WriteToMemory => (up_to_x, 0),
+            Instruction::ReadFromMemory => (0, up_to_x),
+            _ => (0, 0),
+        }
+    }
+
+    fn warn_uninitialized_reads(&mut self, registers: u16) {
+        let address = self.program_counter.wrapping_sub(2);
+        if registers == 0 || !self.uninitialized_warned.insert(address) {
+            return;
+        }
+        (self.warning_callback)(Warning::UninitializedRead { address, registers });
+    }
+
+    // steps over the next instruction, which is four bytes when it's XO-CHIP's F000 NNNN long load
+    fn skip(&mut self) {
+        let next = self.program_counter as usize;
+        let long = self.memory.get(next) == Some(&0xF0) && self.memory.get(next + 1) == Some(&0x00);
+        self.program_counter = self.program_counter.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    fn shift_source(&self, parsed_instruction: &ParsedInstruction) -> u8 {
+        match self.quirks.shift {
+            ShiftMode::VyIntoVx => self.registers[parsed_instruction.y],
+            ShiftMode::InPlace => self.registers[parsed_instruction.x],
+        }
+    }
+
+    fn execute_draw_instruction(
+        &mut self,
+        parsed_instruction: &ParsedInstruction,
+    ) -> Result<(), EmulatorError> {
+        let mut x_pos = self.registers[parsed_instruction.x] as usize;
+        let mut y_pos = self.registers[parsed_instruction.y] as usize;
+        if !self.quirks.clip_start {
+            x_pos %= self.display.width();
+            y_pos %= self.display.height();
+        } else if x_pos >= self.display.width() || y_pos >= self.display.height() {
+            self.registers[0xF] = 0;
+            return Ok(());
+        }
+
+        // in high-res mode DXY0 draws a 16x16 sprite, two bytes per row
+        let (sprite_width, sprite_rows) = if parsed_instruction.n == 0 && self.display.hires() {
+            (16, 16)
         } else {
-            panic!(
-                "Bad draw instruction (memory not found) {}",
-                parsed_instruction.raw_instruction
-            );
+            (8, parsed_instruction.n as usize)
+        };
+        let sprite_len = sprite_rows * sprite_width / 8;
+
+        // with several planes selected, the sprite data for each plane follows the previous one
+        let (mut collided_rows, mut clipped_rows) = (0, 0);
+        let planes: Vec<u8> = self.display.selected_planes().collect();
+        let sprites = self.sprite_bytes(planes.len() * sprite_len)?;
+        for (plane_num, plane) in planes.into_iter().enumerate() {
+            let bytes = &sprites[plane_num * sprite_len..][..sprite_len];
+            let (collided, clipped) =
+                self.draw_sprite_plane(bytes, sprite_width, x_pos, y_pos, plane);
+            collided_rows = collided_rows.max(collided);
+            clipped_rows = clipped_rows.max(clipped);
+        }
+
+        // SUPER-CHIP high-res reports how many rows collided or were clipped, otherwise VF is just
+        // whether anything collided
+        self.registers[0xF] = if self.display.hires() {
+            (collided_rows + clipped_rows) as u8
+        } else {
+            (collided_rows > 0) as u8
         };
 
-        self.registers[0xF] = 0;
+        if collided_rows > 0 {
+            (self.collision_callback)();
+        }
+        Ok(())
+    }
+
+    // returns the number of sprite rows that collided with lit pixels, and the number that fell
+    // off the bottom
+    fn draw_sprite_plane(
+        &mut self,
+        bytes: &[u8],
+        sprite_width: usize,
+        x_pos: usize,
+        y_pos: usize,
+        plane: u8,
+    ) -> (usize, usize) {
+        let bytes_per_row = sprite_width / 8;
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
 
-        for (pos, &byte) in bytes.iter().enumerate() {
-            let draw_y_pos = (y_pos + pos as u8) as usize;
-            if draw_y_pos >= 32 {
-                break;
+        for (pos, row_bytes) in bytes.chunks(bytes_per_row).enumerate() {
+            let mut draw_y_pos = y_pos + pos;
+            if draw_y_pos >= self.display.height() {
+                if self.quirks.wrap_y {
+                    draw_y_pos %= self.display.height();
+                } else {
+                    clipped_rows = bytes.len() / bytes_per_row - pos;
+                    break;
+                }
             }
 
-            for i in 0..8 {
-                if (byte >> (7 - i)) & 0x01 == 0 {
+            let row = row_bytes
+                .iter()
+                .fold(0u16, |row, &byte| (row << 8) | byte as u16);
+            let mut collided = false;
+
+            for i in 0..sprite_width {
+                if (row >> (sprite_width - 1 - i)) & 0x01 == 0 {
                     continue;
                 }
 
-                let draw_x_pos = (x_pos + i) as usize;
+                let mut draw_x_pos = x_pos + i;
 
-                if draw_x_pos >= 64 {
-                    break;
+                if draw_x_pos >= self.display.width() {
+                    if !self.quirks.wrap_x {
+                        break;
+                    }
+                    draw_x_pos %= self.display.width();
                 }
 
-                if self.display.buffer[draw_y_pos][draw_x_pos] {
-                    self.registers[0xF] = 1;
+                match self.quirks.draw {
+                    DrawMode::Xor => {
+                        if self.display.xor_pixel_on(draw_x_pos, draw_y_pos, plane) {
+                            collided = true;
+                        }
+                    }
+                    DrawMode::Or => self.display.or_pixel_on(draw_x_pos, draw_y_pos, plane),
                 }
+            }
 
-                self.display.buffer[draw_y_pos][draw_x_pos] ^= true;
-                self.display.draw = true;
+            if collided {
+                collided_rows += 1;
             }
         }
+
+        (collided_rows, clipped_rows)
     }
 }
 
-pub fn emulate(program: Vec<u8>) {
-    let mut emulator = Emulator::new(program);
-
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let scale_factor = (20, 20);
-    let scale_factor_32 = (scale_factor.0 as u32, scale_factor.1 as u32);
-
-    let width: u16 = 64 * scale_factor.0;
-    let height: u16 = 32 * scale_factor.1;
-
-    let window = video_subsystem
-        .window("CHIP-8 Emulator", width as u32, height as u32)
-        .position_centered()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas().build().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-
-    canvas.set_draw_color(Color::BLACK);
-    canvas.clear();
-    canvas.present();
-
-    let mut last_timer_update = Instant::now();
-    let mut last_instruction_time = Instant::now();
-
-    'running: loop {
-        let elapsed = last_timer_update.elapsed();
-        if elapsed >= Duration::from_millis(16) {
-            if emulator.delay_timer > 0 {
-                emulator.delay_timer -= 1;
-            }
-
-            if emulator.sound_timer > 0 {
-                emulator.sound_timer -= 1;
-            }
-            last_timer_update = Instant::now();
-        }
-
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'running,
-                Event::KeyDown {
-                    keycode: Some(key), ..
-                } => emulator.controller.press_key(key),
-                Event::KeyUp {
-                    keycode: Some(key), ..
-                } => emulator.controller.release_key(key),
-                _ => {}
-            }
-        }
-
-        // Check if it's time to execute the next instruction
-        if last_instruction_time.elapsed() >= Duration::from_micros(25) {
-            emulator.perform_fde_cycle();
-
-            // Rerender if necessary
-            if emulator.display.draw {
-                canvas.set_draw_color(Color::BLUE);
-                canvas.clear();
-                canvas.set_draw_color(Color::YELLOW);
-
-                emulator
-                    .display
-                    .buffer
-                    .iter()
-                    .enumerate()
-                    .for_each(|(col_num, col)| {
-                        col.iter().enumerate().for_each(|(row_num, &val)| {
-                            if val {
-                                let row_num = row_num as i32;
-                                let col_num = col_num as i32;
-
-                                let rect = Rect::new(
-                                    row_num * scale_factor.0 as i32,
-                                    col_num * scale_factor.1 as i32,
-                                    scale_factor_32.0,
-                                    scale_factor_32.1,
-                                );
-
-                                canvas.fill_rect(rect).unwrap();
-                            }
-                        });
-                    });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::LOW_RES_WIDTH;
 
-                // Update the canvas
-                canvas.present();
-                emulator.display.draw = false;
-            }
+    fn execute(registers: &[(usize, u8)], raw_instruction: u16) -> Emulator {
+        let mut initial = [0; 16];
+        for &(register, value) in registers {
+            initial[register] = value;
+        }
+        let mut emulator = Emulator::new(Vec::new()).with_registers(initial);
+        emulator
+            .execute_instruction(ParsedInstruction::parse(raw_instruction).unwrap())
+            .unwrap();
+        emulator
+    }
+
+    #[test]
+    fn fetch_reads_big_endian_words_inside_memory() {
+        let mut emulator = Emulator::new(vec![0x12, 0x34]);
+        assert_eq!(emulator.fetch(), Ok(0x1234));
+
+        emulator.program_counter = (MEMORY_SIZE - 1) as u16;
+        assert_eq!(
+            emulator.fetch(),
+            Err(EmulatorError::ProgramCounterOutOfBounds {
+                address: (MEMORY_SIZE - 1) as u16
+            })
+        );
+    }
+
+    #[test]
+    fn program_counter_wraps_at_the_top_of_64k_memory() {
+        let config = Config {
+            memory_size: XO_CHIP_MEMORY_SIZE,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(Vec::new(), config).unwrap();
+
+        // LD V0, 1 in the last word
+        emulator.memory[0xFFFE..].copy_from_slice(&[0x60, 0x01]);
+        emulator.program_counter = 0xFFFE;
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter, 0);
+
+        // SE V0, 1 skips over the word at 0
+        emulator.memory[0xFFFE..].copy_from_slice(&[0x30, 0x01]);
+        emulator.program_counter = 0xFFFE;
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter, 2);
+
+        // LD I, 0x1234 as F000 in the last word and its address at 0
+        emulator.memory[0xFFFE..].copy_from_slice(&[0xF0, 0x00]);
+        emulator.memory[..2].copy_from_slice(&[0x12, 0x34]);
+        emulator.program_counter = 0xFFFE;
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.index_register, 0x1234);
+        assert_eq!(emulator.program_counter, 2);
+
+        // LD V0, K with no key pressed stays on the last word
+        emulator.memory[0xFFFE..].copy_from_slice(&[0xF0, 0x0A]);
+        emulator.program_counter = 0xFFFE;
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter, 0xFFFE);
+    }
+
+    #[test]
+    fn skips_step_over_the_whole_long_index_load() {
+        // LD V0, 1, SE V0, 1, then LD I, 0x1234 as F000 1234
+        let mut emulator = Emulator::new(vec![0x60, 0x01, 0x30, 0x01, 0xF0, 0x00, 0x12, 0x34]);
+        emulator.perform_fde_cycle().unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter, 0x208);
+
+        let mut emulator = Emulator::new(vec![0x30, 0x00, 0x60, 0x01]);
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter, 0x204);
+    }
+
+    #[test]
+    fn long_index_load_reads_following_word() {
+        let config = Config {
+            memory_size: XO_CHIP_MEMORY_SIZE,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(vec![0xF0, 0x00, 0xBE, 0xEF], config).unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.index_register, 0xBEEF);
+        assert_eq!(emulator.program_counter, 516);
+    }
+
+    #[test]
+    fn draw_only_touches_selected_plane() {
+        let mut emulator = Emulator::new(Vec::new());
+        emulator.index_register = 0x300;
+        emulator.memory[0x300] = 0b1000_0000;
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF201).unwrap())
+            .unwrap();
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+            .unwrap();
+        assert_eq!(emulator.display.buffer[0][0], 0b10);
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF101).unwrap())
+            .unwrap();
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+            .unwrap();
+        assert_eq!(emulator.display.buffer[0][0], 0b11);
+        assert_eq!(emulator.registers[0xF], 0);
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0x00E0).unwrap())
+            .unwrap();
+        assert_eq!(emulator.display.buffer[0][0], 0b10);
+    }
+
+    #[test]
+    fn draw_with_both_planes_reads_consecutive_sprites() {
+        let mut emulator = Emulator::new(Vec::new());
+        emulator.index_register = 0x300;
+        emulator.memory[0x300] = 0b1000_0000;
+        emulator.memory[0x301] = 0b0100_0000;
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF301).unwrap())
+            .unwrap();
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+            .unwrap();
+        assert_eq!(emulator.display.buffer[0][0], 0b01);
+        assert_eq!(emulator.display.buffer[0][1], 0b10);
+    }
+
+    #[test]
+    fn load_audio_pattern_and_pitch() {
+        let mut emulator = execute(&[(0x3, 0x70)], 0xF33A);
+        emulator.index_register = 0x300;
+        emulator.memory[0x300..0x310].copy_from_slice(&[0xAA; 16]);
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF002).unwrap())
+            .unwrap();
+
+        assert_eq!(emulator.pitch, 0x70);
+        assert_eq!(emulator.audio_pattern, Some([0xAA; 16]));
+    }
+
+    #[test]
+    fn call_beyond_max_stack_depth_is_an_error() {
+        let config = Config {
+            max_stack_depth: 2,
+            ..Config::default()
+        };
+        // a subroutine at 0x200 that keeps calling itself
+        let mut emulator = Emulator::with_config(vec![0x22, 0x00], config).unwrap();
+
+        emulator.perform_fde_cycle().unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(
+            emulator.perform_fde_cycle(),
+            Err(EmulatorError::StackOverflow {
+                address: 0x200,
+                depth: 2
+            })
+        );
+        assert_eq!(emulator.stack.len(), 2);
+    }
+
+    #[test]
+    fn profile_counts_each_instruction() {
+        let config = Config {
+            profile: true,
+            ..Config::default()
+        };
+        // LD V0, 1; LD V1, 2; ADD V0, V1
+        let mut emulator =
+            Emulator::with_config(vec![0x60, 0x01, 0x61, 0x02, 0x80, 0x14], config).unwrap();
+        for _ in 0..3 {
+            emulator.perform_fde_cycle().unwrap();
+        }
+
+        let profile = emulator.profile().unwrap();
+        assert_eq!(profile[Instruction::SetRegister as usize], 2);
+        assert_eq!(profile[Instruction::Addition as usize], 1);
+        assert_eq!(profile.iter().sum::<u64>(), 3);
+        assert!(Emulator::new(Vec::new()).profile().is_none());
+    }
+
+    #[test]
+    fn sprite_past_end_of_memory_wraps_only_with_quirk() {
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0xFFE);
+        emulator.memory[0xFFF] = 0x80;
+        emulator.memory[0x000] = 0xFF;
+        let draw = ParsedInstruction::parse(0xD003).unwrap();
+        assert_eq!(
+            emulator.execute_instruction(draw),
+            Err(EmulatorError::MemoryOutOfBounds {
+                address: 0x1FE,
+                index_register: 0xFFE,
+                length: 3
+            })
+        );
+
+        emulator.quirks.wrap_index = true;
+        emulator.execute_instruction(draw).unwrap();
+        assert!(!emulator.display.get_pixel(0, 0));
+        assert!(emulator.display.get_pixel(0, 1) && !emulator.display.get_pixel(1, 1));
+        assert!(emulator.display.get_pixel(7, 2));
+    }
+
+    #[test]
+    fn key_tests_use_the_low_nibble_of_vx() {
+        let mut emulator = Emulator::new(Vec::new()).with_registers([0xFF; 16]);
+        emulator.controller.press(0xF);
+        let program_counter = emulator.program_counter;
+        // SKP V3 skips, SKNP V3 doesn't
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xE39E).unwrap())
+            .unwrap();
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xE3A1).unwrap())
+            .unwrap();
+        assert_eq!(emulator.program_counter, program_counter + 2);
+    }
+
+    #[test]
+    fn step_over_breaks_when_the_call_returns() {
+        // CALL 0x206; LD V0, 1; RET at 0x206 after a nested CALL 0x20A; RET at 0x20A
+        let program = vec![
+            0x22, 0x06, 0x60, 0x01, 0x00, 0x00, 0x22, 0x0A, 0x00, 0xEE, 0x00, 0xEE,
+        ];
+        let mut emulator = Emulator::new(program);
+        assert!(emulator.step_over());
+        let mut cycles = 0;
+        while !emulator.has_break() {
+            emulator.perform_fde_cycle().unwrap();
+            cycles += 1;
+        }
+        assert_eq!(cycles, 4);
+        assert_eq!(
+            emulator.take_break(),
+            Some(Break::SteppedOver { address: 0x202 })
+        );
+
+        // anything but a call is just a step
+        assert!(!emulator.step_over());
+        emulator.perform_fde_cycle().unwrap();
+        assert!(!emulator.has_break());
+    }
+
+    #[test]
+    fn jump_with_offset_wraps_past_end_of_memory() {
+        let emulator = execute(&[(0x0, 0xFF)], 0xBFFF);
+        assert_eq!(emulator.program_counter, 0x0FE);
+    }
+
+    #[test]
+    fn jump_with_offset_reads_vx_with_the_quirk() {
+        // B220 is JP V0, 0x220 normally and JP V2, 0x220 under SUPER-CHIP
+        let registers = [(0x0, 0x01), (0x2, 0x10)];
+        assert_eq!(execute(&registers, 0xB220).program_counter, 0x221);
+
+        let mut emulator = Emulator::new(Vec::new());
+        emulator.quirks = crate::quirks::SUPER_CHIP;
+        emulator.registers[0x0] = 0x01;
+        emulator.registers[0x2] = 0x10;
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xB220).unwrap())
+            .unwrap();
+        assert_eq!(emulator.program_counter, 0x230);
+    }
+
+    #[test]
+    fn super_chip_keeps_index_and_vf() {
+        // LD [I], V1 and OR V0, V1 with VF set beforehand
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0x300);
+        emulator.registers[0xF] = 1;
+        for opcode in [0xF155, 0x8011] {
+            emulator
+                .execute_instruction(ParsedInstruction::parse(opcode).unwrap())
+                .unwrap();
+        }
+        assert_eq!(emulator.index_register, 0x302);
+        assert_eq!(emulator.registers[0xF], 0);
+
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0x300);
+        emulator.quirks = crate::quirks::SUPER_CHIP;
+        emulator.registers[0xF] = 1;
+        for opcode in [0xF155, 0xF165, 0x8011] {
+            emulator
+                .execute_instruction(ParsedInstruction::parse(opcode).unwrap())
+                .unwrap();
+        }
+        assert_eq!(emulator.index_register, 0x300);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn waiting_for_key_until_released() {
+        let mut emulator = Emulator::new(vec![0xF3, 0x0A]);
+        emulator.perform_fde_cycle().unwrap();
+        assert!(emulator.waiting_for_key());
+
+        emulator.controller.press(0x7);
+        emulator.controller.release(0x7);
+        emulator.perform_fde_cycle().unwrap();
+        assert!(!emulator.waiting_for_key());
+        assert_eq!(emulator.registers[0x3], 0x7);
+    }
+
+    #[test]
+    fn return_with_empty_stack_is_an_error() {
+        let mut emulator = Emulator::new(vec![0x00, 0xEE]);
+        assert_eq!(
+            emulator.perform_fde_cycle(),
+            Err(EmulatorError::StackUnderflow { address: 0x200 })
+        );
+    }
+
+    #[test]
+    fn invalid_instruction_is_an_error() {
+        let mut emulator = Emulator::new(vec![0xE0, 0x00]);
+        assert_eq!(
+            emulator.perform_fde_cycle(),
+            Err(EmulatorError::InvalidInstruction {
+                address: 0x200,
+                source: DecodeError {
+                    raw_instruction: 0xE000
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn decode_cache_is_invalidated_by_writes() {
+        let config = Config {
+            decode_cache: true,
+            ..Config::default()
+        };
+        // 0x200: V0 = 0x01, 0x202: V1 = 0xAA, 0x204: jump to 0x202
+        let mut emulator =
+            Emulator::with_config(vec![0x60, 0x01, 0x61, 0xAA, 0x12, 0x02], config).unwrap();
+        for _ in 0..3 {
+            emulator.perform_fde_cycle().unwrap();
+        }
+
+        // overwrite 0x202 with V1 = 0x01 (V0 = 0x01 stored at I = 0x203)
+        emulator.index_register = 0x203;
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF055).unwrap())
+            .unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.registers[0x1], 0x01);
+    }
+
+    #[test]
+    fn hires_draws_16x16_sprites() {
+        let mut emulator = execute(&[(0x0, 100), (0x1, 10)], 0x00FF);
+        emulator.index_register = 0x300;
+        emulator.memory[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD010).unwrap())
+            .unwrap();
+
+        assert_eq!(emulator.display.buffer[10][100], 1);
+        assert_eq!(emulator.display.buffer[25][115], 1);
+        assert_eq!(emulator.display.buffer[26][100], 0);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn hires_collision_counts_rows() {
+        // two rows overlap an existing sprite and four more are clipped at the bottom
+        let mut emulator = execute(&[(0x0, 0), (0x1, 58), (0x2, 56)], 0x00FF);
+        emulator.index_register = 0x300;
+        emulator.memory[0x300..0x30A].copy_from_slice(&[0x80; 10]);
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD012).unwrap())
+            .unwrap();
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD01A).unwrap())
+            .unwrap();
+
+        assert_eq!(emulator.registers[0xF], 6);
+    }
+
+    #[test]
+    fn lores_collision_is_a_flag() {
+        let mut emulator = execute(&[(0x0, 0), (0x1, 0)], 0x00FE);
+        emulator.index_register = 0x300;
+        emulator.memory[0x300..0x304].copy_from_slice(&[0x80; 4]);
+        for _ in 0..2 {
+            emulator
+                .execute_instruction(ParsedInstruction::parse(0xD014).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn or_draw_mode_never_clears_or_collides() {
+        let mut emulator = execute(&[(0x0, 0), (0x1, 0)], 0x00FE);
+        emulator.quirks.draw = DrawMode::Or;
+        emulator.index_register = 0x300;
+        emulator.memory[0x300] = 0xC0;
+        for _ in 0..2 {
+            emulator
+                .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+                .unwrap();
+        }
+
+        assert!(emulator.display.get_pixel(0, 0));
+        assert!(emulator.display.get_pixel(1, 0));
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn last_instruction_is_the_one_just_run() {
+        let mut emulator = Emulator::new(vec![0x60, 0x42, 0x00, 0xE0]);
+        assert!(emulator.last_instruction().is_none());
+        emulator.perform_fde_cycle().unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        let last = emulator.last_instruction().unwrap();
+        assert_eq!(last.raw_instruction, 0x00E0);
+        assert_eq!(last.to_string(), "CLS");
+    }
+
+    #[test]
+    fn strict_mode_notes_reads_of_registers_never_set() {
+        // LD V0, 1 then SE V0, 1 then SE V1, 0
+        let program = vec![0x60, 0x01, 0x30, 0x01, 0x00, 0x00, 0x31, 0x00];
+        let config = Config {
+            strict: true,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(program.clone(), config).unwrap();
+        for _ in 0..3 {
+            emulator.perform_fde_cycle().unwrap();
+        }
+        assert_eq!(emulator.initialized_registers, Some(0b1));
+        assert_eq!(emulator.uninitialized_warned, HashSet::from([0x206]));
+
+        // stepping back before LD V0, 1 forgets that V0 was set
+        emulator.reset(program.clone()).unwrap();
+        let start = emulator.save_state();
+        emulator.perform_fde_cycle().unwrap();
+        emulator.restore_state(start);
+        assert_eq!(emulator.initialized_registers, Some(0));
+
+        let mut emulator = Emulator::new(program);
+        for _ in 0..3 {
+            emulator.perform_fde_cycle().unwrap();
+        }
+        assert!(emulator.uninitialized_warned.is_empty());
+    }
+
+    #[test]
+    fn counts_unique_and_invalid_opcodes() {
+        // LD V0, 1 twice, then JP back to the start, then an invalid instruction never reached
+        let mut emulator = Emulator::new(vec![0x60, 0x01, 0x60, 0x01, 0x12, 0x00]);
+        for _ in 0..6 {
+            emulator.perform_fde_cycle().unwrap();
+        }
+        assert_eq!(emulator.unique_opcodes(), 2);
+        assert_eq!(emulator.invalid_opcodes(), 0);
+
+        let mut emulator = Emulator::new(vec![0xFF, 0xFF]);
+        assert!(emulator.perform_fde_cycle().is_err());
+        assert_eq!(emulator.invalid_opcodes(), 1);
+    }
+
+    #[test]
+    fn program_runs_from_load_address() {
+        let config = Config {
+            load_address: 0x600,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(vec![0x60, 0x42], config).unwrap();
+        assert_eq!(emulator.memory[0x600..0x602], [0x60, 0x42]);
+
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.registers[0x0], 0x42);
+        assert_eq!(emulator.program_counter, 0x602);
+    }
+
+    #[test]
+    fn program_past_end_of_memory_is_an_error() {
+        let config = Config {
+            load_address: 0x600,
+            ..Config::default()
+        };
+        assert_eq!(
+            Emulator::with_config(vec![0; 0xA01], config).err(),
+            Some(EmulatorError::ProgramDoesNotFit {
+                load_address: 0x600,
+                size: 0xA01,
+                memory_size: MEMORY_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn addition_without_carry() {
+        let emulator = execute(&[(0x1, 0x10), (0x2, 0x20)], 0x8124);
+        assert_eq!(emulator.registers[0x1], 0x30);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn addition_with_carry() {
+        let emulator = execute(&[(0x1, 0xFF), (0x2, 0x02)], 0x8124);
+        assert_eq!(emulator.registers[0x1], 0x01);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn addition_into_vf_keeps_flag() {
+        let emulator = execute(&[(0xF, 0xFF), (0x2, 0x02)], 0x8F24);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn subtraction_without_borrow() {
+        let emulator = execute(&[(0x1, 0x30), (0x2, 0x10)], 0x8125);
+        assert_eq!(emulator.registers[0x1], 0x20);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn subtraction_of_equal_values_has_no_borrow() {
+        let emulator = execute(&[(0x1, 0x42), (0x2, 0x42)], 0x8125);
+        assert_eq!(emulator.registers[0x1], 0);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn subtraction_with_borrow() {
+        let emulator = execute(&[(0x1, 0x10), (0x2, 0x30)], 0x8125);
+        assert_eq!(emulator.registers[0x1], 0xE0);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn flipped_subtraction_without_borrow() {
+        let emulator = execute(&[(0x1, 0x10), (0x2, 0x30)], 0x8127);
+        assert_eq!(emulator.registers[0x1], 0x20);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn flipped_subtraction_with_borrow() {
+        let emulator = execute(&[(0x1, 0x30), (0x2, 0x10)], 0x8127);
+        assert_eq!(emulator.registers[0x1], 0xE0);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn right_shift_reads_vy() {
+        let emulator = execute(&[(0x1, 0x00), (0x2, 0b0000_0101)], 0x8126);
+        assert_eq!(emulator.registers[0x1], 0b0000_0010);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn right_shift_without_carry() {
+        let emulator = execute(&[(0x2, 0b0000_0100)], 0x8126);
+        assert_eq!(emulator.registers[0x1], 0b0000_0010);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn left_shift_reads_vy() {
+        let emulator = execute(&[(0x1, 0x00), (0x2, 0b1000_0001)], 0x812E);
+        assert_eq!(emulator.registers[0x1], 0b0000_0010);
+        assert_eq!(emulator.registers[0xF], 1);
+    }
+
+    #[test]
+    fn left_shift_without_carry() {
+        let emulator = execute(&[(0x2, 0b0100_0000)], 0x812E);
+        assert_eq!(emulator.registers[0x1], 0b1000_0000);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn shifts_in_place_ignore_vy() {
+        let config = Config {
+            quirks: Quirks {
+                shift: ShiftMode::InPlace,
+                ..Quirks::default()
+            },
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(Vec::new(), config).unwrap();
+        emulator.registers[0x1] = 0b1000_0011;
+        emulator.registers[0x2] = 0b0000_0100;
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0x8126).unwrap())
+            .unwrap();
+        assert_eq!(emulator.registers[0x1], 0b0100_0001);
+        assert_eq!(emulator.registers[0xF], 1);
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0x812E).unwrap())
+            .unwrap();
+        assert_eq!(emulator.registers[0x1], 0b1000_0010);
+        assert_eq!(emulator.registers[0xF], 0);
+        assert_eq!(emulator.registers[0x2], 0b0000_0100);
+    }
+
+    #[test]
+    fn watch_reports_the_instruction_that_changed_memory() {
+        // I = 0x300, V0 = 0x7B, FX33 stores its digits at 0x300..0x303
+        let mut emulator = Emulator::new(vec![0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33]);
+        emulator.watch(0x301);
+
+        emulator.perform_fde_cycle().unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.take_break(), None);
+
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(
+            emulator.take_break(),
+            Some(Break::Watch {
+                address: 0x301,
+                old: 0,
+                new: 2,
+                program_counter: 0x204
+            })
+        );
+        assert_eq!(emulator.take_break(), None);
+    }
+
+    #[test]
+    fn breakpoint_stops_before_executing_then_resumes() {
+        let mut emulator = Emulator::new(vec![0x60, 0x01, 0x70, 0x01]);
+        emulator.add_breakpoint(0x202);
+
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.take_break(), None);
+
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(
+            emulator.take_break(),
+            Some(Break::Breakpoint { address: 0x202 })
+        );
+        assert_eq!(emulator.program_counter, 0x202);
+        assert_eq!(emulator.registers[0x0], 1);
+
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.take_break(), None);
+        assert_eq!(emulator.registers[0x0], 2);
+    }
+
+    #[test]
+    fn font_can_move_but_not_onto_the_program() {
+        let config = Config {
+            font_address: 0x100,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(Vec::new(), config).unwrap();
+        emulator.registers[0x2] = 0xA;
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF229).unwrap())
+            .unwrap();
+        assert_eq!(emulator.index_register, 0x100 + 5 * 0xA);
+        assert_eq!(emulator.memory[0x132..0x137], font::FONT[50..55]);
+
+        let config = Config {
+            font_address: 0x1F0,
+            ..Config::default()
+        };
+        assert_eq!(
+            Emulator::with_config(vec![0; 4], config).err(),
+            Some(EmulatorError::FontOverlapsProgram {
+                font_address: 0x1F0,
+                load_address: 0x200,
+                size: 4
+            })
+        );
+    }
+
+    #[test]
+    fn collision_callback_fires_when_vf_is_set() {
+        let collisions = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut emulator = Emulator::new(Vec::new());
+        let counter = collisions.clone();
+        emulator.on_collision(move || counter.set(counter.get() + 1));
+
+        // draw the font's 0 twice, the second draw erasing the first
+        emulator.index_register = font::FONT_OFFSET as u16;
+        let draw = ParsedInstruction::parse(0xD005).unwrap();
+        emulator.execute_instruction(draw).unwrap();
+        assert_eq!(collisions.get(), 0);
+        emulator.execute_instruction(draw).unwrap();
+        assert_eq!(collisions.get(), 1);
+    }
+
+    #[test]
+    fn clipping_off_the_bottom_is_not_a_low_res_collision() {
+        let collisions = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut emulator = Emulator::new(Vec::new()).with_registers([0; 16]);
+        let counter = collisions.clone();
+        emulator.on_collision(move || counter.set(counter.get() + 1));
+
+        // the font's 0 drawn at row 30 of an empty screen, so three of its rows fall off
+        emulator.index_register = font::FONT_OFFSET as u16;
+        emulator.registers[0x1] = 30;
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD015).unwrap())
+            .unwrap();
+        assert_eq!(emulator.registers[0xF], 0);
+        assert_eq!(collisions.get(), 0);
+    }
+
+    #[test]
+    fn add_to_index_wraps_at_the_addressable_memory() {
+        // ADD I, V0 with V0 = 1
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0xFFE);
+        emulator.registers[0x0] = 1;
+        let add = ParsedInstruction::parse(0xF01E).unwrap();
+        emulator.execute_instruction(add).unwrap();
+        assert_eq!(
+            (emulator.index_register, emulator.registers[0xF]),
+            (0xFFF, 0)
+        );
+        emulator.execute_instruction(add).unwrap();
+        assert_eq!(
+            (emulator.index_register, emulator.registers[0xF]),
+            (0x000, 1)
+        );
+
+        let config = Config {
+            memory_size: XO_CHIP_MEMORY_SIZE,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(Vec::new(), config)
+            .unwrap()
+            .with_index_register(0xFFF);
+        emulator.registers[0x0] = 1;
+        emulator.execute_instruction(add).unwrap();
+        assert_eq!(
+            (emulator.index_register, emulator.registers[0xF]),
+            (0x1000, 0)
+        );
+        emulator.index_register = 0xFFFF;
+        emulator.execute_instruction(add).unwrap();
+        assert_eq!(emulator.index_register, 0);
+    }
+
+    #[test]
+    fn tick_timers_counts_down_to_zero() {
+        let mut emulator = execute(&[(0x0, 2)], 0xF015);
+        emulator.sound_timer = 1;
+
+        emulator.tick_timers();
+        assert_eq!((emulator.delay_timer, emulator.sound_timer), (1, 0));
+        emulator.tick_timers();
+        emulator.tick_timers();
+        assert_eq!((emulator.delay_timer, emulator.sound_timer), (0, 0));
+    }
 
-            last_instruction_time = Instant::now();
+    #[test]
+    fn beeping_while_the_sound_timer_runs() {
+        let mut emulator = Emulator::new(Vec::new());
+        emulator.set_sound_timer(2);
+        emulator.set_delay_timer(5);
+        assert!(emulator.beeping());
+        emulator.tick_timers();
+        emulator.tick_timers();
+        assert!(!emulator.beeping());
+        assert_eq!((emulator.delay_timer(), emulator.sound_timer()), (3, 0));
+    }
+
+    #[test]
+    fn seeded_emulators_generate_the_same_numbers() {
+        let config = || Config {
+            seed: Some(8),
+            ..Config::default()
+        };
+        let program = vec![0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF];
+        let mut first = Emulator::with_config(program.clone(), config()).unwrap();
+        let mut second = Emulator::with_config(program, config()).unwrap();
+
+        for _ in 0..3 {
+            first.perform_fde_cycle().unwrap();
+            second.perform_fde_cycle().unwrap();
+        }
+        assert_eq!(first.registers, second.registers);
+    }
+
+    #[test]
+    fn cycles_count_executed_instructions() {
+        let mut emulator = Emulator::new(vec![0x60, 0x01, 0x12, 0x00]);
+        emulator.add_breakpoint(0x202);
+
+        emulator.perform_fde_cycle().unwrap();
+        emulator.perform_fde_cycle().unwrap(); // stops at the breakpoint
+        assert_eq!(emulator.cycles(), 1);
+
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.cycles(), 2);
+    }
+
+    #[test]
+    fn machine_call_is_an_error_unless_ignored() {
+        let mut emulator = Emulator::new(vec![0x01, 0x23]);
+        assert_eq!(
+            emulator.perform_fde_cycle(),
+            Err(EmulatorError::MachineCall {
+                address: 0x200,
+                target: 0x123
+            })
+        );
+
+        let config = Config {
+            ignore_machine_calls: true,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(vec![0x01, 0x23], config).unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter, 0x202);
+    }
+
+    #[test]
+    fn odd_program_counter_halts_only_when_asked() {
+        // JP 0x203, then LD V0, 0x12 read across the misaligned bytes
+        let program = vec![0x12, 0x03, 0x00, 0x60, 0x12];
+        let mut emulator = Emulator::new(program.clone());
+        let warnings = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = warnings.clone();
+        emulator.on_warning(move |warning| sink.borrow_mut().push(warning));
+        emulator.perform_fde_cycle().unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.registers[0], 0x12);
+        assert_eq!(
+            *warnings.borrow(),
+            [Warning::MisalignedProgramCounter { address: 0x203 }]
+        );
+
+        let config = Config {
+            halt_on_odd_pc: true,
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(program, config).unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(
+            emulator.perform_fde_cycle(),
+            Err(EmulatorError::MisalignedProgramCounter { address: 0x203 })
+        );
+    }
+
+    #[test]
+    fn sprites_wrap_or_clip_each_axis_independently() {
+        let config = Config {
+            quirks: Quirks {
+                wrap_x: true,
+                ..Quirks::default()
+            },
+            ..Config::default()
+        };
+        let mut emulator = Emulator::with_config(Vec::new(), config).unwrap();
+        emulator.index_register = 0x300;
+        emulator.memory[0x300..0x302].copy_from_slice(&[0xFF, 0xFF]);
+        emulator.registers[0x0] = 60;
+        emulator.registers[0x1] = 31;
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD012).unwrap())
+            .unwrap();
+        assert!(emulator.display.get_pixel(63, 31));
+        assert!(emulator.display.get_pixel(0, 31));
+        assert!(emulator.display.get_pixel(3, 31));
+        assert!(!emulator.display.get_pixel(60, 0));
+    }
+
+    #[test]
+    fn drawing_over_a_filled_screen_collides() {
+        // one pixel at (1, 0) lands on an unlit checkerboard square, one at (0, 0) on a lit one
+        let mut emulator = Emulator::new(Vec::new()).with_fill(&FillPattern::Checkerboard);
+        emulator.index_register = 0x300;
+        emulator.memory[0x300] = 0b0100_0000;
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+            .unwrap();
+        assert_eq!(emulator.registers[0xF], 0);
+
+        emulator.memory[0x300] = 0b1000_0000;
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+            .unwrap();
+        assert_eq!(emulator.registers[0xF], 1);
+        assert!(!emulator.display.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn convert_to_decimal_for_every_value() {
+        let cases = (0..=255u8).map(|value| (value, [value / 100, value / 10 % 10, value % 10]));
+        for (value, digits) in cases {
+            let mut emulator = Emulator::new(Vec::new())
+                .with_registers([value; 16])
+                .with_index_register(0xFFD);
+            emulator
+                .execute_instruction(ParsedInstruction::parse(0xF433).unwrap())
+                .unwrap();
+            assert_eq!(emulator.memory[0xFFD..], digits, "{}", value);
+        }
+
+        // the last three bytes of memory are the highest I can convert into
+        for index_register in [0xFFE, 0xFFF] {
+            let mut emulator = Emulator::new(Vec::new()).with_index_register(index_register);
+            assert!(emulator
+                .execute_instruction(ParsedInstruction::parse(0xF033).unwrap())
+                .is_err());
         }
     }
+
+    #[test]
+    fn memory_access_past_the_end_is_an_error() {
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0xFFE);
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF165).unwrap())
+            .unwrap();
+        assert_eq!(emulator.index_register, 0x1000);
+
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0xFFE);
+        for opcode in [0xF033, 0xF255, 0xF265] {
+            assert_eq!(
+                emulator.execute_instruction(ParsedInstruction::parse(opcode).unwrap()),
+                Err(EmulatorError::MemoryOutOfBounds {
+                    address: 0x1FE,
+                    index_register: 0xFFE,
+                    length: 3
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn audio_pattern_past_the_end_is_an_error() {
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0xFF8);
+        assert_eq!(
+            emulator.execute_instruction(ParsedInstruction::parse(0xF002).unwrap()),
+            Err(EmulatorError::MemoryOutOfBounds {
+                address: 0x1FE,
+                index_register: 0xFF8,
+                length: 16
+            })
+        );
+
+        let mut emulator = Emulator::new(Vec::new()).with_index_register(0xFF0);
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xF002).unwrap())
+            .unwrap();
+        assert_eq!(emulator.audio_pattern, Some([0; 16]));
+    }
+
+    #[test]
+    fn reset_loads_a_new_program_from_the_start() {
+        let mut emulator = Emulator::new(vec![0x60, 0x01, 0x12, 0x02]);
+        emulator.perform_fde_cycle().unwrap();
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter, 0x202);
+
+        emulator.reset(vec![0x61, 0x02]).unwrap();
+        assert_eq!(emulator.program_counter, 0x200);
+        assert_eq!(emulator.registers[0x0], 0);
+        assert_eq!(emulator.cycles(), 0);
+        assert_eq!(emulator.memory[0x202], 0);
+
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.registers[0x1], 2);
+        assert!(emulator.reset(vec![0; MEMORY_SIZE]).is_err());
+    }
+
+    #[test]
+    fn sprite_at_the_right_edge_keeps_its_on_screen_pixels() {
+        let mut emulator = Emulator::new(Vec::new());
+        emulator.index_register = 0x300;
+        emulator.memory[0x300] = 0xFF;
+        emulator.registers[0x0] = 60;
+
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+            .unwrap();
+        let lit: Vec<usize> = (0..LOW_RES_WIDTH)
+            .filter(|&x| emulator.display.get_pixel(x, 0))
+            .collect();
+        assert_eq!(lit, [60, 61, 62, 63]);
+        assert_eq!(emulator.registers[0xF], 0);
+    }
+
+    #[test]
+    fn largest_program_fills_memory_exactly() {
+        let program = vec![0xAA; MEMORY_SIZE - PROGRAM_START as usize];
+        let emulator = Emulator::with_config(program, Config::default()).unwrap();
+        assert_eq!(emulator.memory[MEMORY_SIZE - 1], 0xAA);
+
+        let program = vec![0xAA; MEMORY_SIZE - PROGRAM_START as usize + 1];
+        assert!(Emulator::with_config(program, Config::default()).is_err());
+    }
+
+    #[test]
+    fn initial_index_register_is_used_by_the_first_instruction() {
+        let mut emulator = Emulator::new(vec![0xF0, 0x1E])
+            .with_registers([3; 16])
+            .with_index_register(0x300);
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.index_register, 0x303);
+    }
+
+    #[test]
+    fn tall_mode_draws_on_the_lower_half() {
+        let mut emulator = execute(&[(0x1, 40)], 0x0230);
+        assert_eq!(
+            (emulator.display.width(), emulator.display.height()),
+            (64, 64)
+        );
+
+        emulator.index_register = 0x300;
+        emulator.memory[0x300] = 0x80;
+        emulator
+            .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+            .unwrap();
+        assert_eq!(emulator.display.buffer[40][0], 1);
+    }
+
+    #[test]
+    fn start_position_wraps_unless_clipped() {
+        let draw_at_70 = |quirks| {
+            let config = Config {
+                quirks,
+                ..Config::default()
+            };
+            let mut emulator = Emulator::with_config(Vec::new(), config).unwrap();
+            emulator.index_register = 0x300;
+            emulator.memory[0x300] = 0x80;
+            emulator.registers[0x0] = 70;
+            emulator
+                .execute_instruction(ParsedInstruction::parse(0xD011).unwrap())
+                .unwrap();
+            emulator
+        };
+
+        let wrapped = draw_at_70(Quirks::default());
+        assert_eq!(wrapped.display.buffer[0][6], 1);
+
+        let clipped = draw_at_70(Quirks {
+            clip_start: true,
+            ..Quirks::default()
+        });
+        assert!(clipped
+            .display
+            .rows()
+            .all(|row| row.iter().all(|&val| val == 0)));
+    }
 }
@@ -18,3 +18,4 @@ pub const FONT: [u8; 80] = [
 ];
 
 pub const FONT_OFFSET: usize = 80;
+pub const CHARACTER_HEIGHT: u16 = 5; // bytes per digit
@@ -0,0 +1,173 @@
+// per-ROM settings, read from `<rom>.toml` beside the ROM or a file given with --config:
+//   preset = "superchip"
+//   ips = 700
+//   scale = 15
+//   palette = "amber"
+//
+//   [quirks]
+//   wrap_x = true
+//   shift = "in-place"
+//   draw = "or"
+// only this flat subset of TOML is understood. anything left out falls back to the usual defaults
+use crate::quirks::{DrawMode, QuirkOverrides, ShiftMode};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GameConfig {
+    pub preset: Option<String>,
+    pub quirks: QuirkOverrides, // the [quirks] section
+    pub instructions_per_second: Option<u32>,
+    pub scale: Option<u32>,
+    pub palette: Option<String>,
+}
+
+impl GameConfig {
+    // the `<rom>.toml` beside a ROM, if there is one
+    pub fn beside(rom_path: &Path) -> Option<PathBuf> {
+        let path = rom_path.with_extension("toml");
+        path.is_file().then_some(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        GameConfig::parse(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut config = GameConfig::default();
+        let mut section = String::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |what: &str| format!("line {}: {}", index + 1, what);
+
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                section = name.trim().to_string();
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| invalid("expected key = value"))?;
+            let string = || {
+                value
+                    .strip_prefix('"')
+                    .and_then(|rest| rest.strip_suffix('"'))
+                    .map(str::to_string)
+                    .ok_or_else(|| invalid("expected a quoted string"))
+            };
+            let boolean = || match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(invalid("expected true or false")),
+            };
+            let number = || {
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&number| number > 0)
+                    .ok_or_else(|| invalid("expected a positive whole number"))
+            };
+
+            match (section.as_str(), key) {
+                ("", "preset") => config.preset = Some(string()?),
+                ("", "ips") => config.instructions_per_second = Some(number()?),
+                ("", "scale") => config.scale = Some(number()?),
+                ("", "palette") => config.palette = Some(string()?),
+                ("quirks", "shift") => {
+                    let shift = ShiftMode::named(&string()?)
+                        .ok_or_else(|| invalid("expected \"vy\" or \"in-place\""))?;
+                    config.quirks.shift = Some(shift);
+                }
+                ("quirks", "draw") => {
+                    let draw = DrawMode::named(&string()?)
+                        .ok_or_else(|| invalid("expected \"xor\" or \"or\""))?;
+                    config.quirks.draw = Some(draw);
+                }
+                ("quirks", name) => match config.quirks.flag_mut(name) {
+                    Some(flag) => *flag = Some(boolean()?),
+                    None => return Err(invalid(&format!("unknown setting {}", key))),
+                },
+                _ => return Err(invalid(&format!("unknown setting {}", key))),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+// everything before a # that isn't inside a quoted string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn parses_top_level_settings_and_quirks() {
+        let config = GameConfig::parse(
+            "
+            # Octojam entry
+            preset = \"xochip\"
+            ips = 1000
+            palette = \"amber\"
+
+            [quirks]
+            wrap_y = false  # sprites clip at the bottom
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            GameConfig {
+                preset: Some("xochip".to_string()),
+                quirks: QuirkOverrides {
+                    wrap_y: Some(false),
+                    ..QuirkOverrides::default()
+                },
+                instructions_per_second: Some(1000),
+                palette: Some("amber".to_string()),
+                ..GameConfig::default()
+            }
+        );
+
+        let mut quirks = Quirks::preset("xochip").unwrap();
+        config.quirks.apply(&mut quirks);
+        assert!(quirks.wrap_x && !quirks.wrap_y);
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        assert!(GameConfig::parse("ips = fast").is_err());
+        assert!(GameConfig::parse("scale = 0").is_err());
+        assert!(GameConfig::parse("wrap_x = true").is_err());
+        assert!(GameConfig::parse("[quirks]\nwrap_x = yes").is_err());
+    }
+
+    #[test]
+    fn hashes_inside_quotes_are_not_comments() {
+        let config = GameConfig::parse("palette = \"#1A0F00.json\" # warm").unwrap();
+        assert_eq!(config.palette, Some("#1A0F00.json".to_string()));
+    }
+}
@@ -0,0 +1,138 @@
+// minimal animated GIF encoder for frames of palette indices
+use std::collections::HashMap;
+
+const MAX_CODE_SIZE: u32 = 12;
+const MAX_CODES: u16 = 1 << MAX_CODE_SIZE;
+
+pub struct Frame {
+    pub pixels: Vec<u8>, // one palette index per pixel, row by row
+    pub delay: u16,      // hundredths of a second
+}
+
+// palette is RGB triples, at most 256 entries
+pub fn encode(width: u16, height: u16, palette: &[[u8; 3]], frames: &[Frame]) -> Vec<u8> {
+    // color tables must hold a power of two entries, at least 4 since the minimum LZW code size is 2
+    let table_bits = (palette.len().max(4) as u32)
+        .next_power_of_two()
+        .trailing_zeros();
+    let min_code_size = table_bits.max(2);
+
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend_from_slice(&width.to_le_bytes());
+    gif.extend_from_slice(&height.to_le_bytes());
+    gif.push(0x80 | ((table_bits as u8 - 1) << 4) | (table_bits as u8 - 1)); // global color table
+    gif.extend_from_slice(&[0, 0]); // background color, square pixels
+
+    for index in 0..1 << table_bits {
+        gif.extend_from_slice(palette.get(index).unwrap_or(&[0, 0, 0]));
+    }
+
+    // NETSCAPE2.0 extension, loop forever
+    gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+    gif.extend_from_slice(b"NETSCAPE2.0");
+    gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+    for frame in frames {
+        // graphic control extension carrying the frame delay
+        gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+        gif.extend_from_slice(&frame.delay.to_le_bytes());
+        gif.extend_from_slice(&[0x00, 0x00]);
+
+        // image descriptor covering the whole canvas
+        gif.push(0x2C);
+        gif.extend_from_slice(&[0, 0, 0, 0]);
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&height.to_le_bytes());
+        gif.push(0);
+
+        gif.push(min_code_size as u8);
+        for block in lzw(&frame.pixels, min_code_size).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0);
+    }
+
+    gif.push(0x3B);
+    gif
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    // GIF packs codes least significant bit first
+    fn write(&mut self, code: u16, size: u32) {
+        self.accumulator |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.accumulator as u8);
+            self.accumulator >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.accumulator as u8);
+        }
+        self.bytes
+    }
+}
+
+// writes a code, widening the codes that follow once the next code to be assigned no longer fits
+fn emit(writer: &mut BitWriter, code: u16, next_code: u16, code_size: &mut u32) {
+    writer.write(code, *code_size);
+    if next_code > (1 << *code_size) - 1 && *code_size < MAX_CODE_SIZE {
+        *code_size += 1;
+    }
+}
+
+fn lzw(pixels: &[u8], min_code_size: u32) -> Vec<u8> {
+    let clear_code: u16 = 1 << min_code_size;
+    let end_code = clear_code + 1;
+
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        accumulator: 0,
+        bits: 0,
+    };
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = clear_code + 2;
+    let mut code_size = min_code_size + 1;
+
+    writer.write(clear_code, code_size);
+
+    let mut pixels = pixels.iter();
+    let Some(&first) = pixels.next() else {
+        writer.write(end_code, code_size);
+        return writer.finish();
+    };
+    let mut prefix = first as u16;
+
+    for &pixel in pixels {
+        if let Some(&code) = dictionary.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+
+        emit(&mut writer, prefix, next_code, &mut code_size);
+        if next_code < MAX_CODES {
+            dictionary.insert((prefix, pixel), next_code);
+            next_code += 1;
+        } else {
+            writer.write(clear_code, code_size);
+            dictionary.clear();
+            next_code = clear_code + 2;
+            code_size = min_code_size + 1;
+        }
+        prefix = pixel as u16;
+    }
+
+    emit(&mut writer, prefix, next_code, &mut code_size);
+    writer.write(end_code, code_size);
+    writer.finish()
+}
@@ -0,0 +1,107 @@
+// runs without any display, input or real-time pacing, for scripted and CI runs
+use crate::emulator::{Emulator, EmulatorError};
+use crate::replay::{Input, InputEvent};
+use crate::timer::{CycleClock, DEFAULT_INSTRUCTIONS_PER_SECOND};
+use std::time::{Duration, Instant};
+
+pub struct Options {
+    pub limit_cycles: Option<u64>, // stop once this many instructions have run
+    pub timeout: Option<Duration>, // stop once this much wall-clock time has passed
+    pub instructions_per_second: u32, // timers tick as often as they would at this speed in real time
+    pub ascii_frames: bool, // print the display as text after every instruction that changed it
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            limit_cycles: None,
+            timeout: None,
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            ascii_frames: false,
+        }
+    }
+}
+
+// runs until the limit, or an error. without a limit a ROM that never fails runs forever
+pub fn emulate(
+    mut emulator: Emulator,
+    options: &Options,
+    mut input: Input,
+) -> (Emulator, Result<(), EmulatorError>) {
+    let mut result = Ok(());
+    let mut clock = CycleClock::new(options.instructions_per_second);
+    let started = Instant::now();
+
+    while options
+        .limit_cycles
+        .is_none_or(|limit| emulator.cycles() < limit)
+        && options
+            .timeout
+            .is_none_or(|timeout| started.elapsed() < timeout)
+    {
+        // no breakpoint or watch can be resumed here, so they are only reported
+        if let Some(reason) = emulator.take_break() {
+            println!("{}", reason);
+        }
+
+        for _ in 0..clock.due_ticks(emulator.cycles()) {
+            input.handle(&mut emulator, InputEvent::Tick);
+        }
+        input.before_cycle(&mut emulator);
+        if let Err(err) = emulator.perform_fde_cycle() {
+            result = Err(err);
+            break;
+        }
+
+        // frames are separated by form feeds, so a log can be paged or split a frame at a time
+        if options.ascii_frames && emulator.display.draw {
+            println!("{}\x0c", emulator.display.to_ascii());
+            emulator.display.draw = false;
+        }
+    }
+
+    input.finish();
+
+    (emulator, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_cycle_limit() {
+        // JP 0x200 forever
+        let emulator = Emulator::new(vec![0x12, 0x00]);
+        let options = Options {
+            limit_cycles: Some(1000),
+            ..Options::default()
+        };
+        let (emulator, result) = emulate(emulator, &options, Input::live());
+        assert_eq!(result, Ok(()));
+        assert_eq!(emulator.cycles(), 1000);
+    }
+
+    #[test]
+    fn stops_at_the_timeout() {
+        let emulator = Emulator::new(vec![0x12, 0x00]);
+        let options = Options {
+            timeout: Some(Duration::from_millis(20)),
+            ..Options::default()
+        };
+        let (emulator, result) = emulate(emulator, &options, Input::live());
+        assert_eq!(result, Ok(()));
+        assert!(emulator.cycles() > 0);
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        let emulator = Emulator::new(vec![0x00, 0xEE]);
+        let (emulator, result) = emulate(emulator, &Options::default(), Input::live());
+        assert_eq!(
+            result,
+            Err(EmulatorError::StackUnderflow { address: 0x200 })
+        );
+        assert_eq!(emulator.cycles(), 1);
+    }
+}
@@ -0,0 +1,44 @@
+pub mod assembler;
+#[cfg(feature = "sdl")]
+pub mod audio;
+#[cfg(feature = "sdl")]
+pub mod capture;
+pub mod cartridge;
+pub mod controller;
+pub mod decoder;
+pub mod demo;
+pub mod display;
+pub mod emulator;
+pub mod font;
+pub mod game_config;
+pub mod gif;
+pub mod headless;
+#[cfg(feature = "sdl")]
+pub mod menu;
+pub mod monitor;
+#[cfg(feature = "sdl")]
+pub mod overlay;
+#[cfg(feature = "sdl")]
+pub mod palette;
+pub mod png;
+pub mod profile;
+pub mod quirk_report;
+pub mod quirks;
+pub mod reference;
+pub mod replay;
+pub mod rewind;
+pub mod rom;
+#[cfg(unix)]
+pub mod terminal;
+pub mod timer;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+#[cfg(feature = "sdl")]
+pub mod window;
+
+pub use controller::Controller;
+pub use decoder::{DecodeError, ParsedInstruction};
+pub use display::Display;
+pub use emulator::{Emulator, EmulatorError};
+#[cfg(feature = "sdl")]
+pub use window::emulate;
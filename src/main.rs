@@ -1,18 +1,507 @@
+#[cfg(feature = "sdl")]
+use chip_8::audio::Waveform;
+use chip_8::cartridge::Cartridge;
+use chip_8::controller::Keymap;
+use chip_8::demo::Demo;
+use chip_8::display::FillPattern;
+use chip_8::emulator::{Config, MEMORY_SIZE, PROGRAM_START, XO_CHIP_MEMORY_SIZE};
+use chip_8::font::FONT_OFFSET;
+use chip_8::game_config::GameConfig;
+#[cfg(feature = "sdl")]
+use chip_8::palette::Palette;
+use chip_8::quirks::Quirks;
+use chip_8::reference::{self, Outcome};
+use chip_8::replay::{Input, InputLog};
+use chip_8::rom::{self, Source};
+use chip_8::timer::DEFAULT_INSTRUCTIONS_PER_SECOND;
+use chip_8::{assembler, decoder, profile, quirk_report};
+#[cfg(feature = "sdl")]
+use chip_8::{capture, window};
+use chip_8::{headless, Emulator};
 use std::env;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
 
-mod controller;
-mod decoder;
-mod display;
-mod emulator;
-mod font;
+mod cli;
 
 fn main() {
-    let mut path = env::current_dir().expect("path");
-    path.push("programs");
-    path.push("coraxplus.ch8");
+    let mut args = cli::Args::parse().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
 
-    let program = fs::read(path).unwrap();
+    if let Some(path) = &args.disassemble {
+        disassemble(path, args.load_address.unwrap_or(PROGRAM_START));
+        return;
+    }
+    if let Some((source, output)) = &args.assemble {
+        assemble(source, output, args.load_address.unwrap_or(PROGRAM_START));
+        return;
+    }
 
-    emulator::emulate(program);
+    // a demo carries its own ROM, quirks, seed and input
+    let mut demo = args.play.as_ref().map(|path| {
+        Demo::load(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(2);
+        })
+    });
+    let (rom_path, program) = match (&args.play, &demo) {
+        (Some(path), Some(demo)) => (path.clone(), demo.rom.clone()),
+        _ => {
+            let (rom_path, program) = load_rom(&args);
+            if let Some(warning) = rom::sniff(&rom_path, &program) {
+                eprintln!("Warning: {} {}", rom_path.display(), warning);
+            }
+            (rom_path, program)
+        }
+    };
+
+    // settings saved for this ROM, which flags given on the command line override
+    let game_config = match args
+        .config
+        .clone()
+        .or_else(|| GameConfig::beside(&rom_path))
+    {
+        Some(path) => {
+            let game_config = GameConfig::load(&path).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(2);
+            });
+            println!("Using settings from {}", path.display());
+            game_config
+        }
+        None => GameConfig::default(),
+    };
+    args.preset = args.preset.or(game_config.preset.clone());
+    args.palette = args.palette.or(game_config.palette.clone());
+    args.instructions_per_second = args
+        .instructions_per_second
+        .or(game_config.instructions_per_second);
+    args.scale = args.scale.or(game_config.scale);
+
+    let replay = match demo.as_mut() {
+        Some(demo) => Some(mem::take(&mut demo.input)),
+        None => args.replay_input.as_ref().map(|path| {
+            InputLog::load(path).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(2);
+            })
+        }),
+    };
+
+    // a recording is only replayable with a known seed, so pick one if none was given
+    let seed = args
+        .seed
+        .or(replay.as_ref().and_then(|log| log.seed))
+        .or_else(|| (args.record_input.is_some() || args.record_demo.is_some()).then(rand::random));
+
+    // the preset, then the per-game settings, then individual quirk flags, each overriding the last
+    let quirks = Quirks::resolve(args.preset.as_deref(), &[&game_config.quirks, &args.quirks])
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(2);
+        });
+    if let Some(name) = &args.preset {
+        println!("Using the {} quirks preset", name);
+    }
+
+    let mut config = Config {
+        memory_size: if args.xo_chip {
+            XO_CHIP_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        },
+        decode_cache: args.decode_cache,
+        load_address: args.load_address.unwrap_or(PROGRAM_START),
+        font_address: args.font_address.unwrap_or(FONT_OFFSET as u16),
+        seed,
+        ignore_machine_calls: args.ignore_machine_calls,
+        halt_on_odd_pc: args.halt_on_odd_pc,
+        warn_low_writes: args.warn_low_writes,
+        strict: args.strict,
+        profile: args.profile,
+        quirks,
+        ..Config::default()
+    };
+    if let Some(demo) = &demo {
+        demo.apply(&mut config);
+    }
+
+    let input = match (replay, &args.record_input, &args.record_demo) {
+        (Some(log), _, _) => Input::replay(log),
+        (None, Some(path), _) => Input::recording(path.clone(), seed),
+        (None, None, Some(path)) => {
+            Input::recording_demo(path.clone(), Demo::new(program.clone(), &config))
+        }
+        (None, None, None) => Input::live(),
+    };
+    let mut emulator = Emulator::with_config(program, config).unwrap_or_else(|err| {
+        eprintln!("Could not load {}: {}", rom_path.display(), err);
+        process::exit(1);
+    });
+    emulator.on_warning(|warning| eprintln!("Warning: {}", warning));
+    for &address in &args.watches {
+        emulator.watch(address);
+    }
+    for &address in &args.breakpoints {
+        emulator.add_breakpoint(address);
+    }
+    if let Some(name) = &args.keymap {
+        emulator.controller_mut().keymap = Keymap::named(name).unwrap_or_else(|| {
+            eprintln!("Unknown keymap {}, expected cosmac or natural", name);
+            process::exit(2);
+        });
+    }
+    if let Some(fill) = &args.fill {
+        let pattern = FillPattern::load(fill).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(2);
+        });
+        emulator = emulator.with_fill(&pattern);
+    }
+
+    if args.quirks_report {
+        print_quirks_report(emulator, &args);
+        return;
+    }
+    if let Some(path) = &args.expect_frame {
+        compare_with_reference(emulator, &args, path);
+        return;
+    }
+    if let (Some(cycle), Some(path)) = (args.capture_at_cycle, &args.capture_out) {
+        capture_at_cycle(emulator, &args, cycle, path, input);
+        return;
+    }
+
+    let mut halted = false;
+    let started = Instant::now();
+    let emulator = if args.headless {
+        // headless timers already tick by instruction count, so a per frame budget is just a speed
+        let options = headless::Options {
+            limit_cycles: args.limit_cycles,
+            timeout: args.timeout,
+            instructions_per_second: args
+                .instructions_per_frame
+                .map(|per_frame| per_frame * 60)
+                .or(args.instructions_per_second)
+                .unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+            ascii_frames: args.ascii_frames,
+        };
+        let (emulator, result) = headless::emulate(emulator, &options, input);
+        if let Err(err) = result {
+            eprintln!("Emulation halted: {}", err);
+            halted = true;
+        }
+        emulator
+    } else if args.terminal {
+        run_in_terminal(emulator, &args, input)
+    } else {
+        run_in_window(emulator, &args, input, &rom_path)
+    };
+
+    if let Some(path) = args.dump_memory {
+        match fs::write(&path, emulator.memory()) {
+            Ok(()) => println!("Dumped memory to {}", path.display()),
+            Err(err) => eprintln!("Could not dump memory: {}", err),
+        }
+    }
+
+    let timed_out = args
+        .timeout
+        .is_some_and(|timeout| started.elapsed() >= timeout);
+    if args.limit_cycles.is_some() || timed_out {
+        // a one line health check: a clean run has no invalid opcodes
+        println!(
+            "Ran {} cycles, {} unique opcodes, {} invalid",
+            emulator.cycles(),
+            emulator.unique_opcodes(),
+            emulator.invalid_opcodes()
+        );
+    }
+    if let Some(timeout) = args.timeout {
+        if timed_out {
+            println!("Stopped at the {}s timeout", timeout.as_secs_f64());
+        } else {
+            println!("Finished before the {}s timeout", timeout.as_secs_f64());
+        }
+    }
+    if let Some(profile) = emulator.profile() {
+        print!("{}", profile::report(profile));
+    }
+    if halted {
+        process::exit(1);
+    }
+}
+
+fn programs_dir() -> PathBuf {
+    env::current_dir().expect("path").join("programs")
+}
+
+// asks which ROM to run only when stdin is a terminal, so scripts still get the default
+fn load_rom(args: &cli::Args) -> (PathBuf, Vec<u8>) {
+    if let Some(path) = &args.cart {
+        return load_cartridge_entry(path, args.entry);
+    }
+
+    let can_ask = io::stdin().is_terminal();
+    let rom_path = match rom::source(args.rom.as_deref(), &programs_dir(), can_ask) {
+        Source::File(path) => path,
+        Source::Embedded { name, rom } => return (PathBuf::from(name), rom.to_vec()),
+        Source::Choice(mut roms) => {
+            for (index, rom) in roms.iter().enumerate() {
+                let name = rom.file_name().unwrap_or_default().to_string_lossy();
+                println!("{:>3}  {}", index + 1, name);
+            }
+            let number = ask_for_choice("ROM to run", roms.len());
+            roms.swap_remove(number - 1)
+        }
+    };
+    let program = fs::read(&rom_path).unwrap_or_else(|err| {
+        eprintln!("Could not read {}: {}", rom_path.display(), err);
+        process::exit(1);
+    });
+    (rom_path, program)
+}
+
+// named as if the entry sat beside the cartridge, which is also where its .toml is looked for
+fn load_cartridge_entry(path: &Path, entry: Option<usize>) -> (PathBuf, Vec<u8>) {
+    let cartridge = Cartridge::load(path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    if cartridge.entries.is_empty() {
+        eprintln!("{} holds no ROMs", path.display());
+        process::exit(1);
+    }
+
+    let number = entry.unwrap_or_else(|| {
+        for (index, entry) in cartridge.entries.iter().enumerate() {
+            println!(
+                "{:>3}  {} ({} bytes)",
+                index + 1,
+                entry.name,
+                entry.rom.len()
+            );
+        }
+        ask_for_choice("Entry to run", cartridge.entries.len())
+    });
+    let Some(entry) = cartridge.entries.into_iter().nth(number - 1) else {
+        eprintln!("{} has no entry {}", path.display(), number);
+        process::exit(2);
+    };
+    println!("Running {}", entry.name);
+    (path.with_file_name(&entry.name), entry.rom)
+}
+
+// reads a number between 1 and count from stdin, asking again until it gets one
+fn ask_for_choice(prompt: &str, count: usize) -> usize {
+    let stdin = io::stdin();
+    loop {
+        print!("{} (1-{}): ", prompt, count);
+        io::stdout().flush().expect("stdout");
+        let mut line = String::new();
+        if stdin.read_line(&mut line).unwrap_or(0) == 0 {
+            // stdin closed, so there is nobody to ask
+            process::exit(2);
+        }
+        match line.trim().parse() {
+            Ok(number) if (1..=count).contains(&number) => return number,
+            _ => println!("Expected a number from 1 to {}", count),
+        }
+    }
+}
+
+fn print_quirks_report(emulator: Emulator, args: &cli::Args) {
+    let report = quirk_report::analyse(
+        emulator,
+        args.limit_cycles.unwrap_or(quirk_report::REPORT_CYCLES),
+        args.instructions_per_second
+            .unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+    );
+
+    println!("Watched {} cycles", report.cycles);
+    if let Some(err) = &report.error {
+        println!("Stopped early: {}", err);
+    }
+    let recommendations = report.recommendations();
+    if recommendations.is_empty() {
+        println!("Nothing seen that depends on a quirk");
+    }
+    for recommendation in recommendations {
+        println!("- {}", recommendation);
+    }
+}
+
+fn compare_with_reference(emulator: Emulator, args: &cli::Args, expected: &Path) {
+    let options = headless::Options {
+        limit_cycles: Some(args.limit_cycles.unwrap_or(reference::REFERENCE_CYCLES)),
+        timeout: args.timeout,
+        instructions_per_second: args
+            .instructions_per_second
+            .unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+        ascii_frames: false,
+    };
+    let outcome = reference::check(emulator, &options, expected).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    match outcome {
+        Outcome::Pass => println!("PASS"),
+        Outcome::Fail(diff) => {
+            print!("FAIL\n{}", diff);
+            process::exit(1);
+        }
+        Outcome::Halted(err) => {
+            println!("FAIL\nEmulation halted: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn disassemble(path: &Path, start: u16) {
+    let program = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("Could not read {}: {}", path.display(), err);
+        process::exit(1);
+    });
+    for line in decoder::listing(&program, start) {
+        println!("{}", line);
+    }
+}
+
+fn assemble(source: &Path, output: &Path, origin: u16) {
+    let text = fs::read_to_string(source).unwrap_or_else(|err| {
+        eprintln!("Could not read {}: {}", source.display(), err);
+        process::exit(1);
+    });
+    let program = assembler::assemble(&text, origin).unwrap_or_else(|err| {
+        eprintln!("{}: {}", source.display(), err);
+        process::exit(1);
+    });
+    if let Err(err) = fs::write(output, &program) {
+        eprintln!("Could not write {}: {}", output.display(), err);
+        process::exit(1);
+    }
+    println!("Assembled {} bytes to {}", program.len(), output.display());
+}
+
+#[cfg(unix)]
+fn run_in_terminal(emulator: Emulator, args: &cli::Args, input: Input) -> Emulator {
+    let options = chip_8::terminal::Options {
+        pause_at_start: args.pause_at_start,
+        limit_cycles: args.limit_cycles,
+        timeout: args.timeout,
+        instructions_per_second: args
+            .instructions_per_second
+            .unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+        time_scale: args.time_scale.unwrap_or(1.0),
+    };
+    chip_8::terminal::emulate(emulator, &options, input).expect("terminal")
+}
+
+#[cfg(not(unix))]
+fn run_in_terminal(emulator: Emulator, _args: &cli::Args, _input: Input) -> Emulator {
+    eprintln!("The terminal frontend is only available on unix platforms");
+    emulator
+}
+
+#[cfg(feature = "sdl")]
+fn load_palette(args: &cli::Args) -> Palette {
+    match &args.palette {
+        Some(palette) => {
+            let colors_needed = if args.xo_chip { 4 } else { 2 };
+            Palette::load(palette, colors_needed).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(2);
+            })
+        }
+        None => Palette::default(),
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn capture_at_cycle(emulator: Emulator, args: &cli::Args, cycle: u64, path: &Path, input: Input) {
+    let result = capture::save_at_cycle(
+        emulator,
+        input,
+        cycle,
+        args.instructions_per_second
+            .unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+        path,
+        &load_palette(args),
+        args.scale.unwrap_or(capture::CAPTURE_SCALE),
+    );
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+    println!("Saved cycle {} to {}", cycle, path.display());
+}
+
+#[cfg(not(feature = "sdl"))]
+fn capture_at_cycle(
+    _emulator: Emulator,
+    _args: &cli::Args,
+    _cycle: u64,
+    _path: &Path,
+    _input: Input,
+) {
+    eprintln!("Built without the sdl feature, which --capture-out needs for its palette");
+    process::exit(2);
+}
+
+#[cfg(feature = "sdl")]
+fn run_in_window(emulator: Emulator, args: &cli::Args, input: Input, rom_path: &Path) -> Emulator {
+    let palette = load_palette(args);
+    let waveform = match &args.wave {
+        Some(name) => Waveform::named(name).unwrap_or_else(|| {
+            eprintln!(
+                "Unknown waveform {}, expected square, sine, triangle or noise",
+                name
+            );
+            process::exit(2);
+        }),
+        None => Waveform::default(),
+    };
+
+    let options = window::Options {
+        vsync: args.vsync,
+        palette,
+        record: args.record.clone(),
+        pause_at_start: args.pause_at_start,
+        ghosting: args.ghosting,
+        limit_cycles: args.limit_cycles,
+        timeout: args.timeout,
+        waveform,
+        mute: args.mute,
+        instructions_per_second: args
+            .instructions_per_second
+            .unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+        instructions_per_frame: args.instructions_per_frame,
+        time_scale: args.time_scale.unwrap_or(1.0),
+        scale: args.scale.unwrap_or(window::DEFAULT_SCALE),
+        rewind_depth: args.rewind_depth,
+        hexdump: args.hexdump,
+        test_mode: args.test_mode,
+        watch_file: args.watch_file.then(|| rom_path.to_path_buf()),
+        texture: args.texture,
+        dirty_render: args.dirty_render,
+        slow_on_collision: args.slow_on_collision,
+    };
+    chip_8::emulate(emulator, &options, input)
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_in_window(
+    emulator: Emulator,
+    _args: &cli::Args,
+    _input: Input,
+    _rom_path: &Path,
+) -> Emulator {
+    eprintln!("Built without the sdl feature, so only --terminal and --headless are available");
+    emulator
 }
@@ -0,0 +1,245 @@
+// the settings menu Escape opens over the game. while it's open the machine is paused and keys
+// move through it instead of reaching the ROM: up and down pick a setting, left and right change it
+use crate::overlay::draw_text;
+use crate::palette::{self, Palette};
+use crate::quirks::{DrawMode, Quirks, ShiftMode};
+use sdl2::keyboard::Keycode;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+// speeds the menu steps between. one started at another speed steps to the nearest of these
+const SPEEDS: [u32; 10] = [
+    500, 700, 1_000, 2_000, 5_000, 10_000, 20_000, 40_000, 100_000, 200_000,
+];
+
+// what the menu changes, besides the quirks which live on the emulator
+pub struct Settings {
+    pub instructions_per_second: u32,
+    pub palette: Palette,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Speed,
+    WrapX,
+    WrapY,
+    ClipStart,
+    WrapIndex,
+    JumpUsesVx,
+    KeepIndex,
+    KeepVf,
+    Shift,
+    Draw,
+    Palette,
+    Resume,
+    Quit,
+}
+
+const ITEMS: [Item; 13] = [
+    Item::Speed,
+    Item::WrapX,
+    Item::WrapY,
+    Item::ClipStart,
+    Item::WrapIndex,
+    Item::JumpUsesVx,
+    Item::KeepIndex,
+    Item::KeepVf,
+    Item::Shift,
+    Item::Draw,
+    Item::Palette,
+    Item::Resume,
+    Item::Quit,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    SpeedChanged,
+    Close,
+    Quit,
+}
+
+#[derive(Default)]
+pub struct Menu {
+    pub open: bool,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_key(
+        &mut self,
+        key: Keycode,
+        settings: &mut Settings,
+        quirks: &mut Quirks,
+    ) -> Action {
+        let item = ITEMS[self.selected];
+        match key {
+            Keycode::Escape => Action::Close,
+            Keycode::Up => {
+                self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len();
+                Action::None
+            }
+            Keycode::Down => {
+                self.selected = (self.selected + 1) % ITEMS.len();
+                Action::None
+            }
+            Keycode::Return | Keycode::KpEnter if item == Item::Resume => Action::Close,
+            Keycode::Return | Keycode::KpEnter if item == Item::Quit => Action::Quit,
+            Keycode::Left | Keycode::Right | Keycode::Return | Keycode::KpEnter => {
+                change(item, key != Keycode::Left, settings, quirks)
+            }
+            _ => Action::None,
+        }
+    }
+
+    pub fn lines(&self, settings: &Settings, quirks: &Quirks) -> Vec<String> {
+        let on_off = |on: bool| if on { "ON" } else { "OFF" };
+        ITEMS
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let text = match item {
+                    Item::Speed => format!("SPEED: {} IPS", settings.instructions_per_second),
+                    Item::WrapX => format!("WRAP X: {}", on_off(quirks.wrap_x)),
+                    Item::WrapY => format!("WRAP Y: {}", on_off(quirks.wrap_y)),
+                    Item::ClipStart => format!("CLIP START: {}", on_off(quirks.clip_start)),
+                    Item::WrapIndex => format!("WRAP INDEX: {}", on_off(quirks.wrap_index)),
+                    Item::JumpUsesVx => format!("JUMP VX: {}", on_off(quirks.jump_uses_vx)),
+                    Item::KeepIndex => format!("KEEP INDEX: {}", on_off(quirks.keep_index)),
+                    Item::KeepVf => format!("KEEP VF: {}", on_off(quirks.keep_vf)),
+                    Item::Shift => match quirks.shift {
+                        ShiftMode::VyIntoVx => "SHIFT: VY".to_string(),
+                        ShiftMode::InPlace => "SHIFT: IN-PLACE".to_string(),
+                    },
+                    Item::Draw => format!("DRAW: {}", quirks.draw.name().to_uppercase()),
+                    Item::Palette => format!("PALETTE: {}", palette_name(&settings.palette)),
+                    Item::Resume => "RESUME".to_string(),
+                    Item::Quit => "QUIT".to_string(),
+                };
+                let marker = if index == self.selected { ">" } else { " " };
+                format!("{} {}", marker, text)
+            })
+            .collect()
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        settings: &Settings,
+        quirks: &Quirks,
+        pixel_size: u32,
+    ) {
+        let size = pixel_size as i32;
+        for (row, line) in self.lines(settings, quirks).iter().enumerate() {
+            draw_text(
+                canvas,
+                line,
+                size * 2,
+                size * (2 + 7 * row as i32),
+                pixel_size,
+            );
+        }
+    }
+}
+
+fn change(item: Item, forward: bool, settings: &mut Settings, quirks: &mut Quirks) -> Action {
+    match item {
+        Item::Speed => {
+            let current = settings.instructions_per_second;
+            let next = if forward {
+                SPEEDS.iter().find(|&&speed| speed > current)
+            } else {
+                SPEEDS.iter().rev().find(|&&speed| speed < current)
+            };
+            return match next {
+                Some(&speed) => {
+                    settings.instructions_per_second = speed;
+                    Action::SpeedChanged
+                }
+                None => Action::None,
+            };
+        }
+        Item::WrapX => quirks.wrap_x = !quirks.wrap_x,
+        Item::WrapY => quirks.wrap_y = !quirks.wrap_y,
+        Item::ClipStart => quirks.clip_start = !quirks.clip_start,
+        Item::WrapIndex => quirks.wrap_index = !quirks.wrap_index,
+        Item::JumpUsesVx => quirks.jump_uses_vx = !quirks.jump_uses_vx,
+        Item::KeepIndex => quirks.keep_index = !quirks.keep_index,
+        Item::KeepVf => quirks.keep_vf = !quirks.keep_vf,
+        Item::Shift => {
+            quirks.shift = match quirks.shift {
+                ShiftMode::VyIntoVx => ShiftMode::InPlace,
+                ShiftMode::InPlace => ShiftMode::VyIntoVx,
+            }
+        }
+        Item::Draw => {
+            quirks.draw = match quirks.draw {
+                DrawMode::Xor => DrawMode::Or,
+                DrawMode::Or => DrawMode::Xor,
+            }
+        }
+        Item::Palette => {
+            // a palette loaded from a file isn't in the list, so stepping moves on to the first one
+            let position = palette::BUILTIN
+                .iter()
+                .position(|(_, builtin)| *builtin == settings.palette);
+            let count = palette::BUILTIN.len();
+            let next = match (position, forward) {
+                (Some(position), true) => (position + 1) % count,
+                (Some(position), false) => (position + count - 1) % count,
+                (None, _) => 0,
+            };
+            settings.palette = palette::BUILTIN[next].1;
+        }
+        Item::Resume | Item::Quit => {}
+    }
+    Action::None
+}
+
+fn palette_name(palette: &Palette) -> String {
+    palette::BUILTIN
+        .iter()
+        .find(|(_, builtin)| builtin == palette)
+        .map_or("CUSTOM".to_string(), |(name, _)| name.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_move_through_and_change_settings() {
+        let mut menu = Menu::new();
+        let mut settings = Settings {
+            instructions_per_second: 1_234,
+            palette: palette::CLASSIC,
+        };
+        let mut quirks = Quirks::default();
+
+        assert_eq!(
+            menu.handle_key(Keycode::Right, &mut settings, &mut quirks),
+            Action::SpeedChanged
+        );
+        assert_eq!(settings.instructions_per_second, 2_000);
+
+        menu.handle_key(Keycode::Down, &mut settings, &mut quirks);
+        menu.handle_key(Keycode::Return, &mut settings, &mut quirks);
+        assert!(quirks.wrap_x);
+        assert_eq!(menu.lines(&settings, &quirks)[1], "> WRAP X: ON");
+
+        menu.handle_key(Keycode::Up, &mut settings, &mut quirks);
+        menu.handle_key(Keycode::Up, &mut settings, &mut quirks);
+        assert_eq!(
+            menu.handle_key(Keycode::Return, &mut settings, &mut quirks),
+            Action::Quit
+        );
+        assert_eq!(
+            menu.handle_key(Keycode::Escape, &mut settings, &mut quirks),
+            Action::Close
+        );
+    }
+}
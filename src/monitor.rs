@@ -0,0 +1,180 @@
+// commands typed on stdin while paused, for poking at the machine to test theories:
+//   set V3 0x10 | set I 0x300 | set PC 0x200 | set DT 60 | set ST 0
+//   poke 0x350 0xAB | peek 0x350 [count] | hex [pc|i|0x350] | state
+use crate::emulator::Emulator;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const USAGE: &str = "Commands: set <V0-VF|I|PC|DT|ST> <value>, poke <address> <value>, peek <address> [count], hex [pc|i|address], state";
+const HEXDUMP_ROWS: usize = 8;
+const HEXDUMP_ROWS_BEFORE: usize = 3;
+
+// lines from stdin, read on a separate thread so the frontend never blocks waiting for them
+pub fn spawn_reader() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn parse_number(value: &str) -> Result<u16, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number {}", value))
+}
+
+fn parse_byte(value: &str) -> Result<u8, String> {
+    u8::try_from(parse_number(value)?).map_err(|_| format!("{} does not fit in a byte", value))
+}
+
+// rows of 16 bytes and their ASCII around an address, with its row marked. a few rows come before it
+// so the instructions leading up to the program counter are visible too
+pub fn hexdump(emulator: &Emulator, address: u16) -> String {
+    let memory = emulator.memory();
+    let row_of = address as usize / 16;
+    let first = row_of.saturating_sub(HEXDUMP_ROWS_BEFORE);
+    let last = (first + HEXDUMP_ROWS).min(memory.len().div_ceil(16));
+
+    let mut dump = String::new();
+    for row in first..last {
+        let bytes = &memory[row * 16..(row * 16 + 16).min(memory.len())];
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let marker = if row == row_of { '>' } else { ' ' };
+        dump.push_str(&format!(
+            "{}{:#05x}: {:<47}  |{}|\n",
+            marker,
+            row * 16,
+            hex.join(" "),
+            ascii
+        ));
+    }
+    dump
+}
+
+// runs one command, returning what to print
+pub fn execute(emulator: &mut Emulator, command: &str) -> Result<String, String> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+
+    match words[..] {
+        ["set", target, value] => {
+            let target = target.to_ascii_uppercase();
+            match target.as_str() {
+                "I" => emulator.index_register = parse_number(value)?,
+                "PC" => emulator.program_counter = parse_number(value)?,
+                "DT" => emulator.delay_timer = parse_byte(value)?,
+                "ST" => emulator.sound_timer = parse_byte(value)?,
+                _ => {
+                    let register = target
+                        .strip_prefix('V')
+                        .filter(|digit| digit.len() == 1)
+                        .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                        .ok_or_else(|| format!("Unknown register {}", target))?;
+                    emulator.registers[register] = parse_byte(value)?;
+                }
+            }
+            Ok(format!("{} = {}", target, value))
+        }
+        ["poke", address, value] => {
+            let address = parse_number(address)? as usize;
+            if address >= emulator.memory.len() {
+                return Err(format!("{:#05x} is outside memory", address));
+            }
+            emulator.write_memory(address, parse_byte(value)?);
+            Ok(format!("[{:#05x}] = {}", address, value))
+        }
+        ["peek", address] | ["peek", address, _] => {
+            let start = parse_number(address)? as usize;
+            let count = match words.get(2) {
+                Some(count) => parse_number(count)? as usize,
+                None => 1,
+            };
+            let bytes = emulator
+                .memory
+                .get(start..start + count)
+                .ok_or_else(|| format!("{:#05x} is outside memory", start + count - 1))?;
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            Ok(format!("{:#05x}: {}", start, hex.join(" ")))
+        }
+        ["hex"] => Ok(hexdump(emulator, emulator.program_counter)
+            .trim_end()
+            .to_string()),
+        ["hex", address] => {
+            let address = match address.to_ascii_uppercase().as_str() {
+                "PC" => emulator.program_counter,
+                "I" => emulator.index_register,
+                _ => parse_number(address)?,
+            };
+            if address as usize >= emulator.memory.len() {
+                return Err(format!("{:#05x} is outside memory", address));
+            }
+            Ok(hexdump(emulator, address).trim_end().to_string())
+        }
+        ["state"] => Ok(emulator.dump_state().trim_end().to_string()),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_and_poke_modify_the_machine() {
+        let mut emulator = Emulator::new(Vec::new());
+
+        execute(&mut emulator, "set V3 0x10").unwrap();
+        execute(&mut emulator, "set I 0x300").unwrap();
+        execute(&mut emulator, "poke 0x350 0xAB").unwrap();
+
+        assert_eq!(emulator.registers()[0x3], 0x10);
+        assert_eq!(emulator.index_register(), 0x300);
+        assert_eq!(
+            execute(&mut emulator, "peek 0x350 2"),
+            Ok("0x350: ab 00".to_string())
+        );
+    }
+
+    #[test]
+    fn hexdump_marks_the_row_holding_the_address() {
+        let mut emulator = Emulator::new(b"HI".to_vec());
+        emulator.program_counter = 0x204;
+        let dump = hexdump(&emulator, emulator.program_counter);
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(lines.len(), 8);
+        assert!(lines[0].starts_with(" 0x1d0: "));
+        assert!(lines[3].starts_with(">0x200: 48 49 00"));
+        assert!(lines[3].ends_with("|HI..............|"));
+    }
+
+    #[test]
+    fn bad_commands_are_errors() {
+        let mut emulator = Emulator::new(Vec::new());
+        assert!(execute(&mut emulator, "set VG 1").is_err());
+        assert!(execute(&mut emulator, "set V0 0x100").is_err());
+        assert!(execute(&mut emulator, "poke 0x1000 1").is_err());
+        assert!(execute(&mut emulator, "jump").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::time::{Duration, Instant};
+
+const GLYPH_WIDTH: i32 = 3;
+const GLYPH_HEIGHT: i32 = 5;
+
+const TEXT_COLOR: Color = Color::WHITE;
+const TEXT_BACKGROUND: Color = Color::RGBA(0, 0, 0, 160);
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010], // ?
+    }
+}
+
+// draws text with its top left corner at (x, y) over a translucent backing box
+pub fn draw_text(canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32, pixel_size: u32) {
+    let size = pixel_size as i32;
+    let advance = (GLYPH_WIDTH + 1) * size;
+    let chars = text.chars().count() as i32;
+
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+    canvas.set_draw_color(TEXT_BACKGROUND);
+    canvas
+        .fill_rect(Rect::new(
+            x - size,
+            y - size,
+            (chars * advance + size) as u32,
+            ((GLYPH_HEIGHT + 2) * size) as u32,
+        ))
+        .unwrap();
+
+    canvas.set_draw_color(TEXT_COLOR);
+    for (index, c) in text.chars().enumerate() {
+        let glyph_x = x + index as i32 * advance;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if (bits >> (GLYPH_WIDTH - 1 - col)) & 1 == 1 {
+                    let rect = Rect::new(
+                        glyph_x + col * size,
+                        y + row as i32 * size,
+                        pixel_size,
+                        pixel_size,
+                    );
+                    canvas.fill_rect(rect).unwrap();
+                }
+            }
+        }
+    }
+}
+
+// counts frames and instructions, producing per second figures once a second
+pub struct Throughput {
+    frames: u32,
+    instructions: u32,
+    rects: u32,
+    since: Instant,
+    pub fps: u32,
+    pub ips: u32,
+    pub rects_per_second: u32, // fill_rects the renderer issued, to compare full and dirty redraws
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Throughput {
+            frames: 0,
+            instructions: 0,
+            rects: 0,
+            since: Instant::now(),
+            fps: 0,
+            ips: 0,
+            rects_per_second: 0,
+        }
+    }
+
+    // each presented frame, with the rects drawn for it
+    pub fn frame(&mut self, rects: u32) {
+        self.frames += 1;
+        self.rects += rects;
+    }
+
+    pub fn instruction(&mut self) {
+        self.instructions += 1;
+    }
+
+    // returns true when fps and ips have been refreshed
+    pub fn update(&mut self) -> bool {
+        let elapsed = self.since.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return false;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        self.fps = (self.frames as f64 / seconds).round() as u32;
+        self.ips = (self.instructions as f64 / seconds).round() as u32;
+        self.rects_per_second = (self.rects as f64 / seconds).round() as u32;
+        self.frames = 0;
+        self.instructions = 0;
+        self.rects = 0;
+        self.since = Instant::now();
+        true
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, pixel_size: u32) {
+        let size = pixel_size as i32;
+        draw_text(
+            canvas,
+            &format!("FPS {}", self.fps),
+            size * 2,
+            size * 2,
+            pixel_size,
+        );
+        draw_text(
+            canvas,
+            &format!("IPS {}", self.ips),
+            size * 2,
+            size * 9,
+            pixel_size,
+        );
+        draw_text(
+            canvas,
+            &format!("RECTS {}", self.rects_per_second),
+            size * 2,
+            size * 16,
+            pixel_size,
+        );
+    }
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Throughput::new()
+    }
+}
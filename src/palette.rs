@@ -0,0 +1,131 @@
+use sdl2::pixels::Color;
+use std::fs;
+
+// colors indexed by the planes a pixel is lit on: background, plane 1, plane 2, both planes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub colors: [Color; 4],
+}
+
+pub const CLASSIC: Palette = Palette {
+    colors: [
+        Color::BLUE,
+        Color::YELLOW,
+        Color::RGB(255, 85, 0),
+        Color::WHITE,
+    ],
+};
+
+pub const AMBER: Palette = Palette {
+    colors: [
+        Color::RGB(0x1A, 0x0F, 0x00),
+        Color::RGB(0xFF, 0xB0, 0x00),
+        Color::RGB(0x80, 0x50, 0x00),
+        Color::RGB(0xFF, 0xE0, 0x90),
+    ],
+};
+
+pub const GAMEBOY: Palette = Palette {
+    colors: [
+        Color::RGB(0x9B, 0xBC, 0x0F),
+        Color::RGB(0x0F, 0x38, 0x0F),
+        Color::RGB(0x8B, 0xAC, 0x0F),
+        Color::RGB(0x30, 0x62, 0x30),
+    ],
+};
+
+pub const BUILTIN: [(&str, Palette); 3] =
+    [("classic", CLASSIC), ("amber", AMBER), ("gameboy", GAMEBOY)];
+
+impl Palette {
+    pub fn builtin(name: &str) -> Option<Palette> {
+        BUILTIN
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    // a built-in palette name, or a path to a palette file
+    pub fn load(name_or_path: &str, colors_needed: usize) -> Result<Palette, String> {
+        if let Some(palette) = Palette::builtin(name_or_path) {
+            return Ok(palette);
+        }
+
+        let contents = fs::read_to_string(name_or_path)
+            .map_err(|err| format!("Could not read palette {}: {}", name_or_path, err))?;
+        Palette::parse(&contents, colors_needed)
+    }
+
+    // palette files are a JSON array of "#RRGGBB" strings, background first.
+    // standard CHIP-8 needs 2 colors, XO-CHIP's two planes need 4
+    pub fn parse(contents: &str, colors_needed: usize) -> Result<Palette, String> {
+        let inner = contents
+            .trim()
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .ok_or("Palette must be a JSON array of colors")?;
+
+        let colors = inner
+            .split(',')
+            .map(|entry| parse_color(entry.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if colors.len() != colors_needed {
+            return Err(format!(
+                "Palette has {} colors but {} are needed",
+                colors.len(),
+                colors_needed
+            ));
+        }
+
+        Ok(match colors[..] {
+            [background, foreground] => Palette {
+                colors: [background, foreground, foreground, foreground],
+            },
+            _ => Palette {
+                colors: [colors[0], colors[1], colors[2], colors[3]],
+            },
+        })
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        CLASSIC
+    }
+}
+
+fn parse_color(entry: &str) -> Result<Color, String> {
+    let invalid = || format!("Invalid color {}, expected \"#RRGGBB\"", entry);
+
+    let hex = entry
+        .strip_prefix("\"#")
+        .and_then(|rest| rest.strip_suffix('"'))
+        .filter(|hex| hex.len() == 6)
+        .ok_or_else(invalid)?;
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+
+    Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_two_color_palette() {
+        let palette = Palette::parse(r##"["#000000", "#FF8000"]"##, 2).unwrap();
+        assert_eq!(palette.colors[0], Color::RGB(0, 0, 0));
+        assert_eq!(palette.colors[1], Color::RGB(0xFF, 0x80, 0x00));
+    }
+
+    #[test]
+    fn rejects_wrong_color_count() {
+        assert!(Palette::parse(r##"["#000000", "#FFFFFF"]"##, 4).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_color() {
+        assert!(Palette::parse(r##"["#000000", "white"]"##, 2).is_err());
+    }
+}
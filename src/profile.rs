@@ -0,0 +1,48 @@
+// the instruction counts from Config::profile as a table, most frequent first, with a bar scaled
+// to the most frequent
+use crate::decoder::{Instruction, INSTRUCTION_COUNT};
+
+const BAR_WIDTH: u64 = 40;
+
+pub fn report(profile: &[u64; INSTRUCTION_COUNT]) -> String {
+    let total: u64 = profile.iter().sum();
+    let mut counts: Vec<(Instruction, u64)> = Instruction::ALL
+        .iter()
+        .map(|&instruction| (instruction, profile[instruction as usize]))
+        .filter(|&(_, count)| count > 0)
+        .collect();
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+    let most = counts.first().map_or(1, |&(_, count)| count);
+    let mut report = String::new();
+    for (instruction, count) in counts {
+        report.push_str(&format!(
+            "{:04X} {:<32} {:>12} {:>5.1}% {}\n",
+            instruction.opcode(),
+            format!("{:?}", instruction),
+            count,
+            count as f64 * 100.0 / total as f64,
+            "#".repeat((count * BAR_WIDTH).div_ceil(most) as usize)
+        ));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_instructions_that_ran_most_frequent_first() {
+        let mut profile = [0; INSTRUCTION_COUNT];
+        profile[Instruction::Clear as usize] = 1;
+        profile[Instruction::SetRegister as usize] = 3;
+
+        let report = report(&profile);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("6000 SetRegister"));
+        assert!(lines[0].ends_with(&"#".repeat(40)));
+        assert!(lines[1].contains(" 25.0% "));
+    }
+}
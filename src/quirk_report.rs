@@ -0,0 +1,208 @@
+// runs a ROM for a while watching for instructions whose result depends on which interpreter it was
+// written for, then suggests the quirks and preset to try. it's only a heuristic: code that never runs
+// in the sampled cycles isn't seen, and a pattern showing up doesn't prove the ROM relies on it
+use crate::decoder::{Instruction, ParsedInstruction};
+use crate::emulator::{Emulator, EmulatorError};
+use crate::timer::CycleClock;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct QuirkReport {
+    pub cycles: u64,
+    pub shifts_reading_vy: u64, // 8XY6/8XYE with VX and VY holding different values
+    pub flag_register_operands: u64, // 8XYN writing VF, where the flag and the result compete
+    pub index_reused_after_load_store: u64, // I used straight after FX55/FX65 without being set again
+    pub sprites_past_right_edge: u64,
+    pub sprites_past_bottom_edge: u64,
+    pub sprites_starting_off_screen: u64,
+    pub machine_calls: u64,
+    pub super_chip_instructions: u64, // 00FE/00FF and 16x16 sprites
+    pub xo_chip_instructions: u64,    // F000, FN01, F002 and FX3A
+    pub error: Option<EmulatorError>,
+    after_load_store: bool,
+}
+
+impl QuirkReport {
+    // checks the instruction about to run, before perform_fde_cycle executes it
+    pub fn observe(&mut self, emulator: &Emulator) {
+        let address = emulator.program_counter as usize;
+        let Some(bytes) = emulator.memory.get(address..address + 2) else {
+            return;
+        };
+        let Ok(parsed) = ParsedInstruction::parse(u16::from_be_bytes([bytes[0], bytes[1]])) else {
+            return;
+        };
+        let registers = &emulator.registers;
+        let after_load_store = std::mem::take(&mut self.after_load_store);
+
+        match parsed.instruction {
+            Instruction::RightShift | Instruction::LeftShift
+                if registers[parsed.x] != registers[parsed.y] =>
+            {
+                self.shifts_reading_vy += 1
+            }
+            _ => {}
+        }
+
+        match parsed.instruction {
+            Instruction::LogicalOr
+            | Instruction::LogicalAnd
+            | Instruction::LogicalXor
+            | Instruction::Addition
+            | Instruction::Subtraction
+            | Instruction::RightShift
+            | Instruction::FlippedSubtraction
+            | Instruction::LeftShift
+                if parsed.x == 0xF =>
+            {
+                self.flag_register_operands += 1
+            }
+            Instruction::Draw
+            | Instruction::ConvertToDecimal
+            | Instruction::WriteToMemory
+            | Instruction::ReadFromMemory
+            | Instruction::AddToIndexRegister
+                if after_load_store =>
+            {
+                self.index_reused_after_load_store += 1
+            }
+            _ => {}
+        }
+
+        match parsed.instruction {
+            Instruction::Draw => self.observe_draw(emulator, &parsed),
+            Instruction::WriteToMemory | Instruction::ReadFromMemory => {
+                self.after_load_store = true
+            }
+            Instruction::MachineCall => self.machine_calls += 1,
+            Instruction::LowResolution | Instruction::HighResolution => {
+                self.super_chip_instructions += 1
+            }
+            Instruction::SetIndexRegisterLong
+            | Instruction::SelectPlane
+            | Instruction::LoadAudioPattern
+            | Instruction::SetPitch => self.xo_chip_instructions += 1,
+            _ => {}
+        }
+    }
+
+    fn observe_draw(&mut self, emulator: &Emulator, parsed: &ParsedInstruction) {
+        let display = &emulator.display;
+        let x = emulator.registers[parsed.x] as usize;
+        let y = emulator.registers[parsed.y] as usize;
+        let (width, height) = if parsed.n == 0 && display.hires() {
+            self.super_chip_instructions += 1;
+            (16, 16)
+        } else {
+            (8, parsed.n as usize)
+        };
+
+        if x >= display.width() || y >= display.height() {
+            self.sprites_starting_off_screen += 1;
+            return;
+        }
+        if x + width > display.width() {
+            self.sprites_past_right_edge += 1;
+        }
+        if y + height > display.height() {
+            self.sprites_past_bottom_edge += 1;
+        }
+    }
+
+    pub fn recommendations(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut suggest = |count: u64, advice: &str| {
+            if count > 0 {
+                lines.push(format!("{} ({} times)", advice, count));
+            }
+        };
+
+        suggest(
+            self.xo_chip_instructions,
+            "Uses XO-CHIP instructions: try --preset xochip with --xo-chip",
+        );
+        suggest(
+            self.super_chip_instructions,
+            "Uses SUPER-CHIP instructions: try --preset superchip",
+        );
+        suggest(
+            self.shifts_reading_vy,
+            "Shifts with VX and VY holding different values: if the result looks wrong try --shift in-place, or --shift vy for COSMAC ROMs",
+        );
+        suggest(
+            self.sprites_past_right_edge,
+            "Draws sprites across the right edge: if they should reappear on the left try --wrap-x",
+        );
+        suggest(
+            self.sprites_past_bottom_edge,
+            "Draws sprites across the bottom edge: if they should reappear at the top try --wrap-y",
+        );
+        suggest(
+            self.sprites_starting_off_screen,
+            "Draws sprites starting off screen: if they shouldn't wrap onto it try --clip-start",
+        );
+        suggest(
+            self.machine_calls,
+            "Calls COSMAC machine code: try --ignore-machine-calls",
+        );
+        suggest(
+            self.flag_register_operands,
+            "Uses VF as the target of 8XYN, where interpreters disagree on whether the flag or the result is kept",
+        );
+        suggest(
+            self.index_reused_after_load_store,
+            "Uses I straight after FX55/FX65, relying on it advancing past the registers as on the COSMAC VIP and XO-CHIP",
+        );
+        lines
+    }
+}
+
+// enough for most ROMs to get past their title screen into the game
+pub const REPORT_CYCLES: u64 = 1_000_000;
+
+// runs the ROM for up to this many cycles, without input, ticking the timers at the usual speed
+pub fn analyse(mut emulator: Emulator, cycles: u64, instructions_per_second: u32) -> QuirkReport {
+    let mut report = QuirkReport::default();
+    let mut clock = CycleClock::new(instructions_per_second);
+
+    while emulator.cycles() < cycles {
+        for _ in 0..clock.due_ticks(emulator.cycles()) {
+            emulator.tick_timers();
+        }
+        report.observe(&emulator);
+        if let Err(err) = emulator.perform_fde_cycle() {
+            report.error = Some(err);
+            break;
+        }
+        // breakpoints and watches aren't of interest here, and the next cycle carries on past them
+        emulator.take_break();
+    }
+
+    report.cycles = emulator.cycles();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timer::DEFAULT_INSTRUCTIONS_PER_SECOND;
+
+    #[test]
+    fn spots_shifts_edge_sprites_and_index_reuse() {
+        let program = vec![
+            0x60, 0x3C, // LD V0, 60
+            0x61, 0x05, // LD V1, 5
+            0x80, 0x16, // SHR V0, V1
+            0x60, 0x3C, // LD V0, 60
+            0xF0, 0x55, // LD [I], V0
+            0xD0, 0x11, // DRW V0, V1, 1
+        ];
+        let report = analyse(Emulator::new(program), 6, DEFAULT_INSTRUCTIONS_PER_SECOND);
+
+        assert_eq!(report.cycles, 6);
+        assert_eq!(report.shifts_reading_vy, 1);
+        assert_eq!(report.index_reused_after_load_store, 1);
+        assert_eq!(report.sprites_past_right_edge, 1);
+        assert_eq!(report.sprites_past_bottom_edge, 0);
+        assert_eq!(report.recommendations().len(), 3);
+    }
+}
@@ -0,0 +1,229 @@
+// which register 8XY6 and 8XYE shift
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ShiftMode {
+    #[default]
+    VyIntoVx, // the COSMAC VIP shifts VY and stores the result in VX
+    InPlace, // SUPER-CHIP and most modern interpreters shift VX and ignore VY
+}
+
+impl ShiftMode {
+    pub fn named(name: &str) -> Option<ShiftMode> {
+        match name {
+            "vy" => Some(ShiftMode::VyIntoVx),
+            "in-place" => Some(ShiftMode::InPlace),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShiftMode::VyIntoVx => "vy",
+            ShiftMode::InPlace => "in-place",
+        }
+    }
+}
+
+// how DXYN combines sprite pixels with the screen
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DrawMode {
+    #[default]
+    Xor, // flips pixels and reports collisions in VF, as every standard interpreter does
+    Or, // only ever lights pixels, so nothing collides. for ROMs that draw additively
+}
+
+impl DrawMode {
+    pub fn named(name: &str) -> Option<DrawMode> {
+        match name {
+            "xor" => Some(DrawMode::Xor),
+            "or" => Some(DrawMode::Or),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DrawMode::Xor => "xor",
+            DrawMode::Or => "or",
+        }
+    }
+}
+
+// behaviours that differ between CHIP-8 interpreters, which ROMs written for one of them may rely on
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quirks {
+    pub wrap_x: bool, // sprites drawn past the right edge wrap around to the left instead of being clipped
+    pub wrap_y: bool, // sprites drawn past the bottom edge wrap around to the top instead of being clipped
+    pub clip_start: bool, // a sprite starting off screen isn't drawn, rather than its position wrapping onto the screen
+    pub wrap_index: bool, // sprite data running past the end of memory continues from address 0 instead of being an error
+    pub jump_uses_vx: bool, // BNNN jumps to NNN + VX, X being NNN's high nibble, as SUPER-CHIP does, rather than NNN + V0
+    pub keep_index: bool, // FX55 and FX65 leave I where it was, as SUPER-CHIP does, rather than moving it past the last register
+    pub keep_vf: bool, // 8XY1, 8XY2 and 8XY3 leave VF alone, as SUPER-CHIP and XO-CHIP do, rather than clearing it like the COSMAC VIP
+    pub shift: ShiftMode,
+    pub draw: DrawMode,
+}
+
+// the profiles the community documents for each family of interpreters
+pub const COSMAC: Quirks = Quirks {
+    wrap_x: false,
+    wrap_y: false,
+    clip_start: false,
+    wrap_index: false,
+    jump_uses_vx: false,
+    keep_index: false,
+    keep_vf: false,
+    shift: ShiftMode::VyIntoVx,
+    draw: DrawMode::Xor,
+};
+
+pub const SUPER_CHIP: Quirks = Quirks {
+    wrap_x: false,
+    wrap_y: false,
+    clip_start: false,
+    wrap_index: false,
+    jump_uses_vx: true,
+    keep_index: true,
+    keep_vf: true,
+    shift: ShiftMode::InPlace,
+    draw: DrawMode::Xor,
+};
+
+pub const XO_CHIP: Quirks = Quirks {
+    wrap_x: true,
+    wrap_y: true,
+    clip_start: false,
+    wrap_index: false,
+    jump_uses_vx: false,
+    keep_index: false,
+    keep_vf: true,
+    shift: ShiftMode::VyIntoVx,
+    draw: DrawMode::Xor,
+};
+
+impl Quirks {
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "cosmac" => Some(COSMAC),
+            "superchip" => Some(SUPER_CHIP),
+            "xochip" => Some(XO_CHIP),
+            _ => None,
+        }
+    }
+
+    // the preset, if any, with each layer of overrides applied over the last
+    pub fn resolve(preset: Option<&str>, layers: &[&QuirkOverrides]) -> Result<Quirks, String> {
+        let mut quirks = match preset {
+            Some(name) => Quirks::preset(name).ok_or_else(|| {
+                format!(
+                    "Unknown preset {}, expected cosmac, superchip or xochip",
+                    name
+                )
+            })?,
+            None => Quirks::default(),
+        };
+        for overrides in layers {
+            overrides.apply(&mut quirks);
+        }
+        Ok(quirks)
+    }
+}
+
+// quirks picked individually, by the command line or a game config. only the ones that are set
+// replace the quirk they name, so several of these can be layered over a preset
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QuirkOverrides {
+    pub wrap_x: Option<bool>,
+    pub wrap_y: Option<bool>,
+    pub clip_start: Option<bool>,
+    pub wrap_index: Option<bool>,
+    pub jump_uses_vx: Option<bool>,
+    pub keep_index: Option<bool>,
+    pub keep_vf: Option<bool>,
+    pub shift: Option<ShiftMode>,
+    pub draw: Option<DrawMode>,
+}
+
+impl QuirkOverrides {
+    // the on or off quirk called name, for the parsers to fill in
+    pub fn flag_mut(&mut self, name: &str) -> Option<&mut Option<bool>> {
+        match name {
+            "wrap_x" => Some(&mut self.wrap_x),
+            "wrap_y" => Some(&mut self.wrap_y),
+            "clip_start" => Some(&mut self.clip_start),
+            "wrap_index" => Some(&mut self.wrap_index),
+            "jump_uses_vx" => Some(&mut self.jump_uses_vx),
+            "keep_index" => Some(&mut self.keep_index),
+            "keep_vf" => Some(&mut self.keep_vf),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, quirks: &mut Quirks) {
+        let flags = [
+            (&mut quirks.wrap_x, self.wrap_x),
+            (&mut quirks.wrap_y, self.wrap_y),
+            (&mut quirks.clip_start, self.clip_start),
+            (&mut quirks.wrap_index, self.wrap_index),
+            (&mut quirks.jump_uses_vx, self.jump_uses_vx),
+            (&mut quirks.keep_index, self.keep_index),
+            (&mut quirks.keep_vf, self.keep_vf),
+        ];
+        for (quirk, set) in flags {
+            if let Some(on) = set {
+                *quirk = on;
+            }
+        }
+        if let Some(shift) = self.shift {
+            quirks.shift = shift;
+        }
+        if let Some(draw) = self.draw {
+            quirks.draw = draw;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_only_replace_the_quirks_they_set() {
+        let mut overrides = QuirkOverrides::default();
+        *overrides.flag_mut("wrap_x").unwrap() = Some(false);
+        overrides.shift = Some(ShiftMode::VyIntoVx);
+        assert_eq!(overrides.flag_mut("wrap_z"), None);
+
+        let mut quirks = XO_CHIP;
+        overrides.apply(&mut quirks);
+        assert_eq!(
+            quirks,
+            Quirks {
+                wrap_x: false,
+                ..XO_CHIP
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_layers_overrides_over_the_preset() {
+        let config = QuirkOverrides {
+            wrap_x: Some(false),
+            keep_index: Some(true),
+            ..QuirkOverrides::default()
+        };
+        let command_line = QuirkOverrides {
+            keep_index: Some(false),
+            ..QuirkOverrides::default()
+        };
+
+        let quirks = Quirks::resolve(Some("xochip"), &[&config, &command_line]).unwrap();
+        assert_eq!(
+            quirks,
+            Quirks {
+                wrap_x: false,
+                ..XO_CHIP
+            }
+        );
+        assert_eq!(Quirks::resolve(None, &[]), Ok(Quirks::default()));
+        assert!(Quirks::resolve(Some("chip48"), &[]).is_err());
+    }
+}
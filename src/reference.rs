@@ -0,0 +1,99 @@
+// checks what a test ROM leaves on screen against a frame saved beside it, in the same text form
+// as --ascii-frames, so test suites like Timendus's can be run as pass or fail
+use crate::display::Display;
+use crate::emulator::{Emulator, EmulatorError};
+use crate::headless::{self, Options};
+use crate::replay::Input;
+use std::fs;
+use std::path::Path;
+
+// test ROMs finish well within this and then loop forever on their results screen
+pub const REFERENCE_CYCLES: u64 = 1_000_000;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail(String), // the rows that differ
+    Halted(EmulatorError),
+}
+
+// runs headless for the given cycles, then compares the final frame
+pub fn check(
+    emulator: Emulator,
+    options: &Options,
+    expected_path: &Path,
+) -> Result<Outcome, String> {
+    let expected = fs::read_to_string(expected_path)
+        .map_err(|err| format!("Could not read {}: {}", expected_path.display(), err))?;
+    let (emulator, result) = headless::emulate(emulator, options, Input::live());
+    Ok(match result {
+        Err(err) => Outcome::Halted(err),
+        Ok(()) => match diff(&emulator.display, &expected) {
+            None => Outcome::Pass,
+            Some(diff) => Outcome::Fail(diff),
+        },
+    })
+}
+
+// None when the display matches, otherwise each differing row as expected then actual. trailing
+// blank lines and whitespace in the expected frame are ignored so it can be edited by hand
+pub fn diff(display: &Display, expected: &str) -> Option<String> {
+    let actual = display.to_ascii();
+    let actual: Vec<&str> = actual.lines().collect();
+    let expected: Vec<&str> = expected.trim_end().lines().map(str::trim_end).collect();
+
+    let mut report = String::new();
+    if actual.len() != expected.len() {
+        report.push_str(&format!(
+            "expected {} rows, got {}\n",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    for row in 0..actual.len().max(expected.len()) {
+        let (want, got) = (expected.get(row), actual.get(row));
+        if want != got {
+            report.push_str(&format!(
+                "row {}\n  expected {}\n  got      {}\n",
+                row,
+                want.unwrap_or(&""),
+                got.unwrap_or(&"")
+            ));
+        }
+    }
+    (!report.is_empty()).then_some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_lists_rows_that_differ() {
+        let mut display = Display::new();
+        assert_eq!(diff(&display, &display.to_ascii()), None);
+
+        let expected = display.to_ascii();
+        display.set_pixel(1, 2, true);
+        let report = diff(&display, &expected).unwrap();
+        assert!(report.starts_with("row 2\n"));
+        assert_eq!(report.lines().count(), 3);
+    }
+
+    #[test]
+    fn corax_plus_passes() {
+        let emulator = Emulator::new(include_bytes!("../programs/coraxplus.ch8").to_vec());
+        let options = Options {
+            limit_cycles: Some(100_000),
+            ..Options::default()
+        };
+        assert_eq!(
+            check(
+                emulator,
+                &options,
+                Path::new("programs/expected/coraxplus.txt")
+            ),
+            Ok(Outcome::Pass)
+        );
+    }
+}
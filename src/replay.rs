@@ -0,0 +1,289 @@
+// key presses and timer ticks logged against the cycle they happened before, so a run can be
+// replayed exactly. together with a fixed seed this makes the whole session deterministic
+use crate::demo::Demo;
+use crate::emulator::Emulator;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Press(u8),
+    Release(u8),
+    Tick,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct InputLog {
+    pub seed: Option<u64>,
+    pub events: Vec<(u64, InputEvent)>,
+}
+
+impl InputLog {
+    pub fn new(seed: Option<u64>) -> Self {
+        InputLog {
+            seed,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, cycle: u64, event: InputEvent) {
+        self.events.push((cycle, event));
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        InputLog::parse(&contents)
+    }
+
+    // an optional `seed N` line, then one `<cycle> press|release <key>` or `<cycle> tick` per line
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut log = InputLog::default();
+
+        for line in contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            let invalid = || format!("Invalid input log line {}", line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let key = |field: &str| {
+                u8::from_str_radix(field, 16)
+                    .ok()
+                    .filter(|&key| key < 16)
+                    .ok_or_else(invalid)
+            };
+
+            if let ["seed", seed] = fields[..] {
+                log.seed = Some(seed.parse().map_err(|_| invalid())?);
+                continue;
+            }
+
+            let cycle: u64 = fields[0].parse().map_err(|_| invalid())?;
+            let event = match fields[1..] {
+                ["press", hex] => InputEvent::Press(key(hex)?),
+                ["release", hex] => InputEvent::Release(key(hex)?),
+                ["tick"] => InputEvent::Tick,
+                _ => return Err(invalid()),
+            };
+            log.record(cycle, event);
+        }
+
+        Ok(log)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut contents = String::new();
+        if let Some(seed) = self.seed {
+            contents.push_str(&format!("seed {}\n", seed));
+        }
+        for (cycle, event) in &self.events {
+            let line = match event {
+                InputEvent::Press(key) => format!("{} press {:x}\n", cycle, key),
+                InputEvent::Release(key) => format!("{} release {:x}\n", cycle, key),
+                InputEvent::Tick => format!("{} tick\n", cycle),
+            };
+            contents.push_str(&line);
+        }
+        contents
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+}
+
+// feeds a log back in. frontends call apply before every cycle in place of reading the keyboard and clock
+pub struct Replay {
+    log: InputLog,
+    next: usize,
+}
+
+impl Replay {
+    pub fn new(log: InputLog) -> Self {
+        Replay { log, next: 0 }
+    }
+
+    pub fn apply(&mut self, emulator: &mut Emulator) {
+        while let Some(&(cycle, event)) = self.log.events.get(self.next) {
+            if cycle > emulator.cycles() {
+                break;
+            }
+
+            match event {
+                InputEvent::Press(key) => emulator.controller.press(key),
+                InputEvent::Release(key) => emulator.controller.release(key),
+                InputEvent::Tick => emulator.tick_timers(),
+            }
+            self.next += 1;
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.log.events.len()
+    }
+}
+
+// where a frontend's key presses and timer ticks come from: live, live and logged, or a replayed log
+#[derive(Default)]
+pub struct Input {
+    recording: Option<(PathBuf, InputLog)>,
+    demo: Option<Demo>, // when set the recording is saved inside it rather than as a bare log
+    replay: Option<Replay>,
+}
+
+impl Input {
+    pub fn live() -> Self {
+        Input::default()
+    }
+
+    // seed is written into the log so the replay can use it
+    pub fn recording(path: PathBuf, seed: Option<u64>) -> Self {
+        Input {
+            recording: Some((path, InputLog::new(seed))),
+            ..Input::default()
+        }
+    }
+
+    // records into the demo, which already holds the ROM, config and seed
+    pub fn recording_demo(path: PathBuf, demo: Demo) -> Self {
+        Input {
+            recording: Some((path, InputLog::new(demo.input.seed))),
+            demo: Some(demo),
+            replay: None,
+        }
+    }
+
+    pub fn replay(log: InputLog) -> Self {
+        Input {
+            replay: Some(Replay::new(log)),
+            ..Input::default()
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // live events are dropped while replaying, since the log already holds them
+    pub fn handle(&mut self, emulator: &mut Emulator, event: InputEvent) {
+        if self.replay.is_some() {
+            return;
+        }
+
+        match event {
+            InputEvent::Press(key) => emulator.controller.press(key),
+            InputEvent::Release(key) => emulator.controller.release(key),
+            InputEvent::Tick => emulator.tick_timers(),
+        }
+        if let Some((_, log)) = self.recording.as_mut() {
+            log.record(emulator.cycles(), event);
+        }
+    }
+
+    // after the emulator steps back to this cycle, forgets the logged events from later on so a
+    // recording or a replay carries on in step with it. events at the cycle itself came before
+    // the saved state. held keys aren't saved, so they stay as they are now
+    pub fn rewind(&mut self, cycle: u64) {
+        if let Some((_, log)) = self.recording.as_mut() {
+            log.events.retain(|&(at, _)| at <= cycle);
+        }
+        if let Some(replay) = self.replay.as_mut() {
+            replay.next = replay.log.events.partition_point(|&(at, _)| at <= cycle);
+        }
+    }
+
+    pub fn before_cycle(&mut self, emulator: &mut Emulator) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.apply(emulator);
+        }
+    }
+
+    // writes out the log when recording
+    pub fn finish(self) {
+        if let Some((path, log)) = self.recording {
+            let saved = match self.demo {
+                Some(mut demo) => {
+                    demo.input = log;
+                    demo.save(&path)
+                }
+                None => log.save(&path),
+            };
+            match saved {
+                Ok(()) => println!("Saved input to {}", path.display()),
+                Err(err) => eprintln!("Could not save input: {}", err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_round_trips_through_text() {
+        let mut log = InputLog::new(Some(7));
+        log.record(0, InputEvent::Tick);
+        log.record(120, InputEvent::Press(0xA));
+        log.record(180, InputEvent::Release(0xA));
+
+        let path = std::env::temp_dir().join("chip-8-input-log-test.txt");
+        log.save(&path).unwrap();
+        let loaded = InputLog::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, log);
+    }
+
+    #[test]
+    fn replay_applies_events_at_their_cycle() {
+        // LD V0, 1 then loop on SKP V0 / JP
+        let mut emulator = Emulator::new(vec![0x60, 0x01, 0xE0, 0x9E, 0x12, 0x02, 0x00, 0xE0]);
+        let mut log = InputLog::new(None);
+        log.record(3, InputEvent::Press(0x1));
+        let mut replay = Replay::new(log);
+
+        for _ in 0..3 {
+            replay.apply(&mut emulator);
+            emulator.perform_fde_cycle().unwrap();
+        }
+        assert_eq!(emulator.program_counter(), 0x202);
+        assert!(!replay.finished());
+
+        replay.apply(&mut emulator);
+        emulator.perform_fde_cycle().unwrap();
+        assert_eq!(emulator.program_counter(), 0x206);
+        assert!(replay.finished());
+    }
+
+    #[test]
+    fn rewinding_drops_later_events() {
+        // JP 0x200, looping in place
+        let mut emulator = Emulator::new(vec![0x12, 0x00]);
+        let run = |emulator: &mut Emulator, cycles| {
+            for _ in 0..cycles {
+                emulator.perform_fde_cycle().unwrap();
+            }
+        };
+        let mut input = Input::recording(PathBuf::from("unused"), None);
+        input.handle(&mut emulator, InputEvent::Press(0x1));
+        run(&mut emulator, 5);
+        input.handle(&mut emulator, InputEvent::Release(0x1));
+        input.rewind(4);
+        assert_eq!(
+            input.recording.as_ref().unwrap().1.events,
+            [(0, InputEvent::Press(0x1))]
+        );
+
+        let mut log = InputLog::new(None);
+        log.record(2, InputEvent::Tick);
+        log.record(6, InputEvent::Tick);
+        let mut input = Input::replay(log);
+        run(&mut emulator, 5);
+        input.before_cycle(&mut emulator);
+        input.rewind(4);
+        assert_eq!(input.replay.as_ref().unwrap().next, 1);
+    }
+}
@@ -0,0 +1,75 @@
+// the most recent machine states, saved before each cycle so the debugger can step backwards. the
+// input log isn't part of them, so a frontend that steps back also calls Input::rewind
+use crate::emulator::{Emulator, EmulatorState};
+use std::collections::VecDeque;
+
+pub struct Rewind {
+    states: VecDeque<EmulatorState>,
+    depth: usize, // cycles kept, the oldest dropped first
+}
+
+impl Rewind {
+    pub fn new(depth: usize) -> Self {
+        Rewind {
+            // grown as cycles are recorded, since a large depth of 64 KiB states may never fill
+            states: VecDeque::new(),
+            depth,
+        }
+    }
+
+    pub fn record(&mut self, emulator: &Emulator) {
+        if self.depth == 0 {
+            return;
+        }
+        if self.states.len() == self.depth {
+            self.states.pop_front();
+        }
+        self.states.push_back(emulator.save_state());
+    }
+
+    // undoes the last recorded cycle, false once there is nothing left to undo
+    pub fn step_back(&mut self, emulator: &mut Emulator) -> bool {
+        match self.states.pop_back() {
+            Some(state) => {
+                emulator.restore_state(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_back_through_recorded_cycles() {
+        // LD V0, 1 / ADD V0, 1 / ADD V0, 1
+        let mut emulator = Emulator::new(vec![0x60, 0x01, 0x70, 0x01, 0x70, 0x01]);
+        let mut rewind = Rewind::new(2);
+        for _ in 0..3 {
+            rewind.record(&emulator);
+            emulator.perform_fde_cycle().unwrap();
+        }
+        assert_eq!(emulator.registers()[0], 3);
+        assert_eq!(rewind.len(), 2);
+
+        assert!(rewind.step_back(&mut emulator));
+        assert_eq!(emulator.registers()[0], 2);
+        assert_eq!(emulator.program_counter(), 0x204);
+        assert_eq!(emulator.cycles(), 2);
+
+        assert!(rewind.step_back(&mut emulator));
+        assert_eq!(emulator.registers()[0], 1);
+        assert!(!rewind.step_back(&mut emulator));
+    }
+}
@@ -0,0 +1,150 @@
+// finding ROM files and spotting files that aren't ROMs, for frontends choosing what to run
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the fallback when no ROM is given on the command line
+pub const ROM_VARIABLE: &str = "CHIP8_ROM";
+
+pub const ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "bin"];
+
+// file signatures that are easy to grab instead of a ROM
+const NON_ROM_SIGNATURES: [(&[u8], &str); 7] = [
+    (b"\x89PNG", "PNG image"),
+    (b"GIF8", "GIF image"),
+    (b"\xFF\xD8\xFF", "JPEG image"),
+    (b"%PDF", "PDF document"),
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"\x7FELF", "ELF executable"),
+    (b"MZ", "Windows executable"),
+];
+
+pub fn has_rom_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+// run when nothing else is picked
+pub const DEFAULT_ROM: &str = "coraxplus.ch8";
+
+// demo builds carry a ROM so the binary runs without the programs directory beside it
+#[cfg(feature = "embedded-rom")]
+const EMBEDDED_ROM: Option<&[u8]> = Some(include_bytes!("../programs/coraxplus.ch8"));
+#[cfg(not(feature = "embedded-rom"))]
+const EMBEDDED_ROM: Option<&[u8]> = None;
+
+// where the ROM to run comes from
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    File(PathBuf),
+    Embedded {
+        name: &'static str,
+        rom: &'static [u8],
+    },
+    // the ROMs in the programs directory, for the frontend to ask which to run
+    Choice(Vec<PathBuf>),
+}
+
+// the ROM given, then the one named by CHIP8_ROM, otherwise the embedded one. without those, the
+// ROMs in programs to choose from when there's someone to ask, or its default ROM
+pub fn source(given: Option<&Path>, programs: &Path, can_ask: bool) -> Source {
+    let named = given.map(Path::to_path_buf).or_else(|| {
+        env::var_os(ROM_VARIABLE)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    if let Some(path) = named {
+        return Source::File(path);
+    }
+    if let Some(rom) = EMBEDDED_ROM {
+        return Source::Embedded {
+            name: DEFAULT_ROM,
+            rom,
+        };
+    }
+
+    let roms = if can_ask { list(programs) } else { Vec::new() };
+    if roms.is_empty() {
+        Source::File(programs.join(DEFAULT_ROM))
+    } else {
+        Source::Choice(roms)
+    }
+}
+
+// the ROM files in a directory, sorted by name. empty if it can't be read
+pub fn list(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && has_rom_extension(path))
+        .collect();
+    roms.sort();
+    roms
+}
+
+// a warning when the file doesn't look like a ROM. it may still run, so this never stops it loading.
+// ROMs too large for memory are already rejected when the emulator is created
+pub fn sniff(path: &Path, program: &[u8]) -> Option<String> {
+    if let Some((_, format)) = NON_ROM_SIGNATURES
+        .iter()
+        .find(|(signature, _)| program.starts_with(signature))
+    {
+        return Some(format!("looks like a {}, not a CHIP-8 ROM", format));
+    }
+    if program.is_empty() {
+        return Some("is empty".to_string());
+    }
+    if !has_rom_extension(path) {
+        return Some("does not have a CHIP-8 ROM extension".to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_warns_about_files_that_are_not_roms() {
+        let rom = [0x00, 0xE0];
+        assert_eq!(sniff(Path::new("pong.CH8"), &rom), None);
+        assert_eq!(
+            sniff(Path::new("pong.png"), b"\x89PNG\r\n"),
+            Some("looks like a PNG image, not a CHIP-8 ROM".to_string())
+        );
+        assert_eq!(
+            sniff(Path::new("pong.ch8"), &[]),
+            Some("is empty".to_string())
+        );
+        assert!(sniff(Path::new("pong.txt"), &rom).is_some());
+    }
+
+    #[cfg(not(feature = "embedded-rom"))]
+    #[test]
+    fn source_asks_only_when_no_rom_is_named() {
+        let programs = Path::new("programs");
+        let given = Path::new("pong.ch8");
+        assert_eq!(
+            source(Some(given), programs, true),
+            Source::File(given.to_path_buf())
+        );
+        if env::var_os(ROM_VARIABLE).is_none() {
+            assert_eq!(
+                source(None, programs, false),
+                Source::File(programs.join(DEFAULT_ROM))
+            );
+            assert!(matches!(source(None, programs, true), Source::Choice(_)));
+        }
+    }
+
+    #[test]
+    fn lists_only_rom_files() {
+        let roms = list(Path::new("programs"));
+        assert!(roms.contains(&PathBuf::from("programs/coraxplus.ch8")));
+        assert!(roms.iter().all(|path| has_rom_extension(path)));
+        assert!(roms.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
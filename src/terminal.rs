@@ -0,0 +1,222 @@
+use crate::emulator::Emulator;
+use crate::replay::{Input, InputEvent};
+use crate::timer::{self, TimerClock};
+use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
+use std::time::{Duration, Instant};
+
+// terminals only report key presses, so a key is treated as held until no repeat has arrived for this long
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+const PAUSE_KEY: u8 = b' ';
+const STEP_KEY: u8 = b'n';
+const STEP_OVER_KEY: u8 = b'o';
+
+// puts stdin into raw, non-blocking mode and restores the original settings when dropped
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let mut termios = MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = unsafe { termios.assume_init() };
+
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+        // leave the alternate screen and show the cursor again
+        print!("\x1b[?1049l\x1b[?25h");
+        let _ = io::stdout().flush();
+    }
+}
+
+// each character cell holds two vertically stacked pixels
+fn render(emulator: &Emulator, status: Option<&str>, out: &mut impl Write) -> io::Result<()> {
+    let buffer: Vec<&[u8]> = emulator.display.rows().collect();
+    // clear below the frame too, in case the resolution shrank
+    let mut frame = String::from("\x1b[H\x1b[J");
+
+    for rows in buffer.chunks(2) {
+        for col in 0..rows[0].len() {
+            let top = rows[0][col] != 0;
+            let bottom = rows.get(1).is_some_and(|row| row[col] != 0);
+            frame.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        frame.push_str("\r\n");
+    }
+
+    if let Some(status) = status {
+        frame.push_str(status);
+        if !status.ends_with('\n') {
+            frame.push_str("\r\n");
+        }
+    }
+
+    out.write_all(frame.as_bytes())?;
+    out.flush()
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub pause_at_start: bool, // wait for the step or resume key before the first instruction
+    pub limit_cycles: Option<u64>, // quit once this many instructions have run
+    pub timeout: Option<Duration>, // quit once this much wall-clock time has passed
+    pub instructions_per_second: u32,
+    pub time_scale: f64, // slows or speeds up instructions and timers together
+}
+
+pub fn emulate(
+    mut emulator: Emulator,
+    options: &Options,
+    mut input: Input,
+) -> io::Result<Emulator> {
+    let raw_mode = RawMode::enable()?;
+    let mut stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    // switch to the alternate screen, clear it and hide the cursor
+    stdout.write_all(b"\x1b[?1049h\x1b[2J\x1b[?25l")?;
+    stdout.flush()?;
+    if options.pause_at_start {
+        render(&emulator, Some("Paused"), &mut stdout)?;
+    }
+
+    let mut held_keys: [Option<Instant>; 16] = [None; 16];
+    let mut keys = [0u8; 32];
+
+    let mut timer_clock = TimerClock::scaled(options.time_scale);
+    let instruction_interval = timer::scale_interval(
+        Duration::from_secs(1) / options.instructions_per_second.max(1),
+        options.time_scale,
+    );
+    let mut last_instruction_time = Instant::now();
+
+    let mut error = None;
+    let mut paused = options.pause_at_start;
+    let mut step = false;
+    let mut waiting_for_key = false;
+
+    let started = Instant::now();
+
+    'running: loop {
+        if options
+            .limit_cycles
+            .is_some_and(|limit| emulator.cycles() >= limit)
+            || options
+                .timeout
+                .is_some_and(|timeout| started.elapsed() >= timeout)
+        {
+            break;
+        }
+
+        let mut ticks = timer_clock.due_ticks().min(u8::MAX as u32) as u8;
+        if paused {
+            ticks = 0;
+        }
+        for _ in 0..ticks {
+            input.handle(&mut emulator, InputEvent::Tick);
+        }
+
+        let read = stdin.read(&mut keys)?;
+        for &key in &keys[..read] {
+            // escape or ctrl-c quits
+            if key == 0x1B || key == 0x03 {
+                break 'running;
+            }
+
+            if key == PAUSE_KEY {
+                paused = !paused;
+                let status = if paused { "Paused" } else { "Resumed" };
+                render(&emulator, Some(status), &mut stdout)?;
+                continue;
+            }
+            if key == STEP_KEY {
+                step = paused;
+                continue;
+            }
+            if key == STEP_OVER_KEY && paused {
+                if emulator.step_over() {
+                    paused = false;
+                } else {
+                    step = true;
+                }
+                continue;
+            }
+
+            if let Some(hex) = emulator.controller().keymap.hex_for(key) {
+                input.handle(&mut emulator, InputEvent::Press(hex));
+                held_keys[hex as usize] = Some(Instant::now());
+            }
+        }
+
+        for (hex, held) in held_keys.iter_mut().enumerate() {
+            if held.is_some_and(|pressed_at| pressed_at.elapsed() >= KEY_HOLD) {
+                input.handle(&mut emulator, InputEvent::Release(hex as u8));
+                *held = None;
+            }
+        }
+
+        if paused && !step {
+            std::thread::sleep(Duration::from_millis(16));
+            continue;
+        }
+
+        // Check if it's time to execute the next instruction
+        if step || last_instruction_time.elapsed() >= instruction_interval {
+            input.before_cycle(&mut emulator);
+            if let Err(err) = emulator.perform_fde_cycle() {
+                error = Some(err);
+                break 'running;
+            }
+            step = false;
+
+            if let Some(reason) = emulator.take_break() {
+                paused = true;
+                let status = format!("{}\n{}", reason, emulator.dump_state());
+                render(&emulator, Some(&status.replace('\n', "\r\n")), &mut stdout)?;
+                emulator.display.draw = false;
+            } else if emulator.waiting_for_key() != waiting_for_key {
+                waiting_for_key = emulator.waiting_for_key();
+                let status = waiting_for_key.then_some("Waiting for a key");
+                render(&emulator, status, &mut stdout)?;
+                emulator.display.draw = false;
+            } else if emulator.display.draw {
+                render(&emulator, None, &mut stdout)?;
+                emulator.display.draw = false;
+            }
+
+            last_instruction_time = Instant::now();
+        }
+    }
+
+    // restore the terminal first so the message isn't lost on the alternate screen
+    drop(raw_mode);
+    if let Some(err) = error {
+        eprintln!("Emulation halted: {}", err);
+    }
+
+    input.finish();
+
+    Ok(emulator)
+}
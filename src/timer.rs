@@ -0,0 +1,149 @@
+use std::time::{Duration, Instant};
+
+// delay and sound timers count down at 60Hz
+pub const TIMER_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 40_000;
+
+// the slowest and fastest --time-scale. beyond these the scaled intervals stop being usable
+pub const MIN_TIME_SCALE: f64 = 0.01;
+pub const MAX_TIME_SCALE: f64 = 100.0;
+
+// interval / time_scale, kept to at least a nanosecond and saturating instead of panicking when
+// the result doesn't fit in a Duration
+pub fn scale_interval(interval: Duration, time_scale: f64) -> Duration {
+    Duration::try_from_secs_f64(interval.as_secs_f64() / time_scale)
+        .unwrap_or(Duration::MAX)
+        .max(Duration::from_nanos(1))
+}
+
+// keeps the 60Hz timer schedule independent of how often the instruction loop polls it
+pub struct TimerClock {
+    last_update: Instant,
+    interval: Duration,
+}
+
+impl TimerClock {
+    pub fn new() -> Self {
+        TimerClock::scaled(1.0)
+    }
+
+    // ticks spaced out by 1 / time_scale, so 0.5 ticks at 30Hz to match a half speed CPU
+    pub fn scaled(time_scale: f64) -> Self {
+        TimerClock {
+            last_update: Instant::now(),
+            interval: scale_interval(TIMER_INTERVAL, time_scale),
+        }
+    }
+
+    // number of timer ticks that have elapsed since the last call
+    pub fn due_ticks(&mut self) -> u32 {
+        self.due_ticks_at(Instant::now())
+    }
+
+    // every whole interval that has passed is returned at once, so a slow loop catches up
+    // instead of losing ticks. the leftover time is kept for the next call
+    fn due_ticks_at(&mut self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.last_update);
+        let ticks = (elapsed.as_nanos() / self.interval.as_nanos()) as u32;
+
+        self.last_update += self.interval * ticks;
+        ticks
+    }
+}
+
+impl Default for TimerClock {
+    fn default() -> Self {
+        TimerClock::new()
+    }
+}
+
+// ticks the timers every so many instructions instead of by the wall clock, so a run that isn't
+// paced in real time still sees 60Hz timers relative to the instructions it executes
+pub struct CycleClock {
+    cycles_per_tick: u64,
+    next_tick: u64, // the first tick is due straight away, at cycle 0
+}
+
+impl CycleClock {
+    pub fn new(instructions_per_second: u32) -> Self {
+        CycleClock {
+            cycles_per_tick: (instructions_per_second as u64 / 60).max(1),
+            next_tick: 0,
+        }
+    }
+
+    // number of ticks due once this many instructions have run
+    pub fn due_ticks(&mut self, cycles: u64) -> u32 {
+        let mut ticks = 0;
+        while cycles >= self.next_tick {
+            self.next_tick += self.cycles_per_tick;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    // ticks from the next one on are spaced for the new speed
+    pub fn set_speed(&mut self, instructions_per_second: u32) {
+        self.cycles_per_tick = (instructions_per_second as u64 / 60).max(1);
+    }
+
+    pub fn next_tick(&self) -> u64 {
+        self.next_tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_ticks_before_interval() {
+        let mut clock = TimerClock::new();
+        let start = clock.last_update;
+        assert_eq!(clock.due_ticks_at(start + TIMER_INTERVAL / 2), 0);
+    }
+
+    #[test]
+    fn catches_up_multiple_ticks() {
+        let mut clock = TimerClock::new();
+        let start = clock.last_update;
+        assert_eq!(clock.due_ticks_at(start + TIMER_INTERVAL * 3), 3);
+        assert_eq!(clock.last_update, start + TIMER_INTERVAL * 3);
+    }
+
+    #[test]
+    fn extreme_scales_keep_a_usable_interval() {
+        assert_eq!(
+            scale_interval(TIMER_INTERVAL, f64::MAX),
+            Duration::from_nanos(1)
+        );
+        assert_eq!(
+            scale_interval(TIMER_INTERVAL, f64::MIN_POSITIVE),
+            Duration::MAX
+        );
+
+        let mut clock = TimerClock::scaled(1e300);
+        let start = clock.last_update;
+        assert_eq!(clock.due_ticks_at(start + Duration::from_nanos(5)), 5);
+    }
+
+    #[test]
+    fn cycle_clock_ticks_every_sixtieth_of_a_second_of_instructions() {
+        let mut clock = CycleClock::new(600);
+        assert_eq!(clock.due_ticks(0), 1);
+        assert_eq!(clock.due_ticks(9), 0);
+        assert_eq!(clock.due_ticks(10), 1);
+        assert_eq!(clock.due_ticks(35), 2);
+        assert_eq!(clock.next_tick(), 40);
+    }
+
+    #[test]
+    fn keeps_remainder_for_next_tick() {
+        let mut clock = TimerClock::new();
+        let start = clock.last_update;
+        let half = TIMER_INTERVAL / 2;
+
+        assert_eq!(clock.due_ticks_at(start + TIMER_INTERVAL * 2 + half), 2);
+        assert_eq!(clock.due_ticks_at(start + TIMER_INTERVAL * 3), 1);
+    }
+}
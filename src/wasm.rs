@@ -0,0 +1,60 @@
+// wasm-bindgen bindings for running in the browser. build the module and its JavaScript glue with
+// the commands at the top of web/index.html, which then drives a Chip8 once per animation frame
+use crate::emulator::Config;
+use crate::Emulator;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct Chip8 {
+    emulator: Emulator,
+}
+
+#[wasm_bindgen]
+impl Chip8 {
+    // throws if the ROM doesn't fit in memory
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], seed: u64) -> Result<Chip8, JsError> {
+        let config = Config {
+            seed: Some(seed),
+            ..Config::default()
+        };
+        Emulator::with_config(rom.to_vec(), config)
+            .map(|emulator| Chip8 { emulator })
+            .map_err(|err| JsError::new(&err.to_string()))
+    }
+
+    // one fetch-decode-execute cycle. returns false once the emulator has halted on an error
+    pub fn step(&mut self) -> bool {
+        self.emulator.perform_fde_cycle().is_ok()
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.emulator.tick_timers();
+    }
+
+    // true while the sound timer is running, for the page to start and stop a tone
+    pub fn sound_on(&self) -> bool {
+        self.emulator.beeping()
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.emulator.controller_mut().press(key);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.emulator.controller_mut().release(key);
+    }
+
+    pub fn display_width(&self) -> usize {
+        self.emulator.display().width()
+    }
+
+    pub fn display_height(&self) -> usize {
+        self.emulator.display().height()
+    }
+
+    // the visible part of Display::buffer, one byte of plane bits per pixel, row by row
+    pub fn display(&self) -> Vec<u8> {
+        self.emulator.display().rows().flatten().copied().collect()
+    }
+}
@@ -0,0 +1,985 @@
+use crate::audio::{Audio, Waveform};
+use crate::capture;
+use crate::decoder::ParsedInstruction;
+use crate::display::{Display, Resolution, HIGH_RES_HEIGHT, HIGH_RES_WIDTH};
+use crate::emulator::{Emulator, EmulatorError};
+use crate::menu::{Action, Menu, Settings};
+use crate::monitor;
+use crate::overlay::{draw_text, Throughput};
+use crate::palette::Palette;
+use crate::replay::{Input, InputEvent};
+use crate::rewind::Rewind;
+use crate::timer::{self, CycleClock, TimerClock};
+use sdl2::controller::GameController;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_SCALE: u32 = 20;
+
+const STATS_KEY: Keycode = Keycode::F1;
+const SCREENSHOT_KEY: Keycode = Keycode::F12;
+const PAUSE_KEY: Keycode = Keycode::F5;
+const STEP_KEY: Keycode = Keycode::F10;
+const STEP_OVER_KEY: Keycode = Keycode::F11;
+const REWIND_KEY: Keycode = Keycode::Backspace;
+const TURBO_KEY: Keycode = Keycode::Tab;
+const MUTE_KEY: Keycode = Keycode::F3;
+// while turbo is held instructions run flat out for this long each pass, leaving time for input and rendering
+const TURBO_SLICE: Duration = Duration::from_millis(10);
+const OVERLAY_PIXEL_SIZE: u32 = 3;
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+const GRID_KEY: Keycode = Keycode::F2;
+const OPCODE_KEY: Keycode = Keycode::F4;
+const SOUND_BAR_KEY: Keycode = Keycode::F6;
+const SOUND_BAR_COLOR: Color = Color::RGB(255, 170, 0);
+const GRID_COLOR: Color = Color::RGBA(128, 128, 128, 48);
+const GRID_BYTE_COLOR: Color = Color::RGBA(128, 128, 128, 112);
+// with slow motion on collision, each collision runs this many 60Hz frames this many times slower
+const SLOW_MOTION_FRAMES: u32 = 20;
+const SLOW_MOTION_FACTOR: u32 = 8;
+// with ghosting on, a pixel that turns off takes this many 60Hz frames to fade out
+const GHOST_FRAMES: u8 = 4;
+
+#[derive(Default)]
+pub struct Options {
+    pub vsync: bool, // present once per display refresh instead of after every draw
+    pub palette: Palette,
+    pub record: Option<PathBuf>, // write an animated GIF of the session here on quit
+    pub pause_at_start: bool,    // wait for the step or resume key before the first instruction
+    pub ghosting: bool, // fade pixels out over a few frames, like CRT persistence, to soften XOR flicker
+    pub limit_cycles: Option<u64>, // quit once this many instructions have run
+    pub timeout: Option<Duration>, // quit once this much wall-clock time has passed
+    pub waveform: Waveform,
+    pub mute: bool, // start with the sound off, F3 turns it back on
+    pub instructions_per_second: u32,
+    pub instructions_per_frame: Option<u32>, // run this many after each 60Hz timer tick, ignoring instructions_per_second
+    pub time_scale: f64, // slows or speeds up instructions and timers together, 1.0 for real time
+    pub scale: u32,      // window pixels per CHIP-8 pixel at the starting resolution
+    pub rewind_depth: usize, // cycles Backspace can step back through, 0 to not record them
+    pub hexdump: bool,   // print memory around PC and I whenever the machine pauses or steps
+    pub test_mode: bool, // run flat out, ticking the timers by instruction count rather than the wall clock
+    pub watch_file: Option<PathBuf>, // restart with this ROM whenever it changes on disk
+    pub texture: bool, // draw each frame through one streaming texture rather than a rect per pixel
+    pub dirty_render: bool, // redraw only the pixels that changed. ignored with texture or ghosting
+    pub slow_on_collision: bool, // briefly slow down whenever a draw collides, to see where it happened
+}
+
+// std has no portable change notification, so the modification time is polled every so often
+struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl FileWatcher {
+    fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        FileWatcher {
+            path,
+            modified,
+            last_check: Instant::now(),
+        }
+    }
+
+    fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let modified = modified_time(&self.path);
+        let changed = modified.is_some() && modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// brightness the renderer keeps for every pixel, separate from the display's on/off state
+struct Ghosting {
+    intensity: [[u8; HIGH_RES_WIDTH]; HIGH_RES_HEIGHT], // GHOST_FRAMES while lit, counting down once off
+    planes: [[u8; HIGH_RES_WIDTH]; HIGH_RES_HEIGHT], // what a fading pixel was last lit with, for its color
+}
+
+impl Ghosting {
+    fn new() -> Self {
+        Ghosting {
+            intensity: [[0; HIGH_RES_WIDTH]; HIGH_RES_HEIGHT],
+            planes: [[0; HIGH_RES_WIDTH]; HIGH_RES_HEIGHT],
+        }
+    }
+
+    // advances one frame, returning whether any pixel faded and needs redrawing
+    fn update(&mut self, display: &Display) -> bool {
+        let mut faded = false;
+        for (y, row) in display.rows().enumerate() {
+            for (x, &val) in row.iter().enumerate() {
+                if val != 0 {
+                    self.intensity[y][x] = GHOST_FRAMES;
+                    self.planes[y][x] = val;
+                } else if self.intensity[y][x] > 0 {
+                    self.intensity[y][x] -= 1;
+                    faded = true;
+                }
+            }
+        }
+        faded
+    }
+
+    // the color of an unlit pixel that is still fading, if any
+    fn color(&self, x: usize, y: usize, palette: &Palette) -> Option<Color> {
+        let intensity = self.intensity[y][x];
+        if intensity == 0 {
+            return None;
+        }
+
+        let amount = intensity as f32 / GHOST_FRAMES as f32;
+        let from = palette.colors[0];
+        let to = palette.colors[self.planes[y][x] as usize];
+        let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount) as u8;
+        Some(Color::RGB(
+            mix(from.r, to.r),
+            mix(from.g, to.g),
+            mix(from.b, to.b),
+        ))
+    }
+}
+
+// the size of each CHIP-8 pixel and the borders left of and above the screen. high-res mode fits
+// twice as many pixels into the same window. pixels stay square, so when the window's shape doesn't
+// match the display's, as with the 64x64 mode or after a resize, the screen is centred between borders
+fn layout(display: &Display, window_size: (u32, u32)) -> (u32, (i32, i32)) {
+    let pixel_size = (window_size.0 / display.width() as u32)
+        .min(window_size.1 / display.height() as u32)
+        .max(1);
+    let border =
+        |window: u32, pixels: usize| (window.saturating_sub(pixels as u32 * pixel_size) / 2) as i32;
+    (
+        pixel_size,
+        (
+            border(window_size.0, display.width()),
+            border(window_size.1, display.height()),
+        ),
+    )
+}
+
+// faint lines between every pixel, brighter every 8 to line up with sprite bytes
+fn draw_grid(canvas: &mut Canvas<Window>, display: &Display, window_size: (u32, u32)) {
+    let (pixel_size, (left, top)) = layout(display, window_size);
+    let right = left + (display.width() as u32 * pixel_size) as i32;
+    let bottom = top + (display.height() as u32 * pixel_size) as i32;
+
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+    let line_color = |index: usize| {
+        if index.is_multiple_of(8) {
+            GRID_BYTE_COLOR
+        } else {
+            GRID_COLOR
+        }
+    };
+    for x in 0..=display.width() {
+        canvas.set_draw_color(line_color(x));
+        let x = left + (x as u32 * pixel_size) as i32;
+        canvas.draw_line((x, top), (x, bottom)).unwrap();
+    }
+    for y in 0..=display.height() {
+        canvas.set_draw_color(line_color(y));
+        let y = top + (y as u32 * pixel_size) as i32;
+        canvas.draw_line((left, y), (right, y)).unwrap();
+    }
+}
+
+fn render(
+    canvas: &mut Canvas<Window>,
+    display: &Display,
+    palette: &Palette,
+    ghosting: Option<&Ghosting>,
+    window_size: (u32, u32),
+) -> u32 {
+    canvas.set_draw_color(palette.colors[0]);
+    canvas.clear();
+
+    let (pixel_size, (left, top)) = layout(display, window_size);
+    let scale_factor = (pixel_size, pixel_size);
+    let mut drawn = 0;
+
+    display.rows().enumerate().for_each(|(col_num, col)| {
+        col.iter().enumerate().for_each(|(row_num, &val)| {
+            let color = if val != 0 {
+                Some(palette.colors[val as usize])
+            } else {
+                ghosting.and_then(|ghosting| ghosting.color(row_num, col_num, palette))
+            };
+
+            if let Some(color) = color {
+                canvas.set_draw_color(color);
+
+                let row_num = row_num as i32;
+                let col_num = col_num as i32;
+
+                let rect = Rect::new(
+                    left + row_num * scale_factor.0 as i32,
+                    top + col_num * scale_factor.1 as i32,
+                    scale_factor.0,
+                    scale_factor.1,
+                );
+
+                canvas.fill_rect(rect).unwrap();
+                drawn += 1;
+            }
+        });
+    });
+    drawn
+}
+
+// draws only the pixels that changed into a texture kept between frames, then copies that onto
+// the canvas, whose own contents are lost on every present. a new resolution or palette redraws
+// everything. returns the rects filled, like render
+fn render_dirty(
+    canvas: &mut Canvas<Window>,
+    target: &mut Texture,
+    drawn_with: &mut Option<(Resolution, Palette)>,
+    display: &mut Display,
+    palette: &Palette,
+    window_size: (u32, u32),
+) -> u32 {
+    let dirty = display.take_dirty();
+    let full = *drawn_with != Some((display.resolution, *palette));
+    *drawn_with = Some((display.resolution, *palette));
+
+    let (pixel_size, (left, top)) = layout(display, window_size);
+    let mut drawn = 0;
+    canvas
+        .with_texture_canvas(target, |target| {
+            if full {
+                target.set_draw_color(palette.colors[0]);
+                target.clear();
+            }
+            for (y, row) in display.rows().enumerate() {
+                for (x, &val) in row.iter().enumerate() {
+                    let changed = dirty[y] & (1 << x) != 0;
+                    // a full redraw starts from a cleared target, so only lit pixels are needed
+                    if (full && val == 0) || (!full && !changed) {
+                        continue;
+                    }
+                    target.set_draw_color(palette.colors[val as usize]);
+                    target
+                        .fill_rect(Rect::new(
+                            left + (x as u32 * pixel_size) as i32,
+                            top + (y as u32 * pixel_size) as i32,
+                            pixel_size,
+                            pixel_size,
+                        ))
+                        .unwrap();
+                    drawn += 1;
+                }
+            }
+        })
+        .unwrap();
+    canvas.copy(target, None, None).unwrap();
+    drawn
+}
+
+// the same frame written pixel by pixel into a texture the size of the largest resolution, then
+// scaled onto the canvas with a single copy instead of a fill_rect for every lit pixel
+fn render_texture(
+    canvas: &mut Canvas<Window>,
+    texture: &mut Texture,
+    display: &Display,
+    palette: &Palette,
+    ghosting: Option<&Ghosting>,
+    window_size: (u32, u32),
+) -> u32 {
+    canvas.set_draw_color(palette.colors[0]);
+    canvas.clear();
+
+    texture
+        .with_lock(None, |pixels: &mut [u8], pitch: usize| {
+            for (y, row) in display.rows().enumerate() {
+                for (x, &val) in row.iter().enumerate() {
+                    let color = if val != 0 {
+                        palette.colors[val as usize]
+                    } else {
+                        ghosting
+                            .and_then(|ghosting| ghosting.color(x, y, palette))
+                            .unwrap_or(palette.colors[0])
+                    };
+                    let offset = y * pitch + x * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&[color.r, color.g, color.b]);
+                }
+            }
+        })
+        .unwrap();
+
+    let (pixel_size, (left, top)) = layout(display, window_size);
+    let (width, height) = (display.width() as u32, display.height() as u32);
+    canvas
+        .copy(
+            texture,
+            Rect::new(0, 0, width, height),
+            Rect::new(left, top, width * pixel_size, height * pixel_size),
+        )
+        .unwrap();
+    1
+}
+
+// the raw opcode and its mnemonic in the bottom right corner
+fn draw_opcode(
+    canvas: &mut Canvas<Window>,
+    instruction: &ParsedInstruction,
+    window_size: (u32, u32),
+) {
+    let size = OVERLAY_PIXEL_SIZE as i32;
+    let text = format!("{:04X} {}", instruction.raw_instruction, instruction);
+    let text_width = text.chars().count() as i32 * 4 * size;
+    draw_text(
+        canvas,
+        &text,
+        window_size.0 as i32 - text_width - size * 2,
+        window_size.1 as i32 - size * 7,
+        OVERLAY_PIXEL_SIZE,
+    );
+}
+
+// the sound timer in the top right corner, its bar an overlay pixel per remaining tick so it
+// shrinks as the beep plays out
+fn draw_sound_bar(canvas: &mut Canvas<Window>, sound_timer: u8, window_width: u32) {
+    let size = OVERLAY_PIXEL_SIZE as i32;
+    // never more than half the window, so the label stays on screen in small windows
+    let bar_width = (sound_timer as i32 * size).min(window_width as i32 / 2);
+    let label = format!("ST {:>3}", sound_timer);
+    let label_width = label.len() as i32 * 4 * size;
+    let right = window_width as i32 - size * 2;
+    draw_text(
+        canvas,
+        &label,
+        right - bar_width - label_width - size,
+        size * 2,
+        OVERLAY_PIXEL_SIZE,
+    );
+    if sound_timer > 0 {
+        canvas.set_draw_color(SOUND_BAR_COLOR);
+        canvas
+            .fill_rect(Rect::new(
+                right - bar_width,
+                size * 2,
+                bar_width as u32,
+                5 * OVERLAY_PIXEL_SIZE,
+            ))
+            .unwrap();
+    }
+}
+
+fn run_cycle(
+    emulator: &mut Emulator,
+    input: &mut Input,
+    rewind: &mut Rewind,
+) -> Result<(), EmulatorError> {
+    input.before_cycle(emulator);
+    rewind.record(emulator);
+    emulator.perform_fde_cycle()
+}
+
+// runs until the window is closed, handing back the emulator in its final state
+pub fn emulate(mut emulator: Emulator, options: &Options, mut input: Input) -> Emulator {
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+
+    // sized for the resolution the ROM starts in. render scales later mode changes and resizes to fit
+    let mut width = emulator.display.width() as u32 * options.scale;
+    let mut height = emulator.display.height() as u32 * options.scale;
+    let interval_at = |instructions_per_second: u32| {
+        timer::scale_interval(
+            Duration::from_secs(1) / instructions_per_second,
+            options.time_scale,
+        )
+    };
+    let mut instruction_interval = interval_at(options.instructions_per_second);
+
+    let window = video_subsystem
+        .window("CHIP-8 Emulator", width, height)
+        .position_centered()
+        .resizable()
+        .build()
+        .unwrap();
+
+    let mut canvas_builder = window.into_canvas();
+    if options.vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas = canvas_builder.build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = options.texture.then(|| {
+        texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                HIGH_RES_WIDTH as u32,
+                HIGH_RES_HEIGHT as u32,
+            )
+            .unwrap()
+    });
+    let mut dirty_target =
+        (options.dirty_render && !options.texture && !options.ghosting).then(|| {
+            texture_creator
+                .create_texture_target(None, width, height)
+                .unwrap()
+        });
+    let mut drawn_with = None; // what dirty_target holds, so a change to either redraws it all
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    // controllers are opened as they connect, which also covers any plugged in before launch
+    let game_controller_subsystem = match sdl_context.game_controller() {
+        Ok(subsystem) => Some(subsystem),
+        Err(err) => {
+            eprintln!(
+                "Gamepads unavailable, continuing with the keyboard only: {}",
+                err
+            );
+            None
+        }
+    };
+    let mut gamepads: Vec<GameController> = Vec::new();
+
+    let mut audio = match Audio::new(&sdl_context, options.waveform, options.mute) {
+        Ok(audio) => Some(audio),
+        Err(err) => {
+            eprintln!("Audio unavailable, continuing without sound: {}", err);
+            None
+        }
+    };
+
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+    canvas.present();
+
+    let mut timer_clock = TimerClock::scaled(options.time_scale);
+    let mut cycle_clock = options
+        .test_mode
+        .then(|| CycleClock::new(options.instructions_per_second));
+    let mut last_instruction_time = Instant::now();
+    let mut halted = false; // set when the ROM hits an error, leaving the last frame on screen
+    let mut paused = options.pause_at_start;
+    let mut step = false; // run a single instruction while paused
+    let mut hexdump_due = false; // the machine just paused or moved while paused
+    let mut rewind = Rewind::new(options.rewind_depth);
+    let mut file_watcher = options.watch_file.clone().map(FileWatcher::new);
+    let mut turbo = false;
+
+    let mut ghosting = options.ghosting.then(Ghosting::new);
+
+    // set by the collision callback and taken each pass round the loop
+    let collided = options.slow_on_collision.then(|| Rc::new(Cell::new(false)));
+    if let Some(collided) = &collided {
+        let collided = Rc::clone(collided);
+        emulator.on_collision(move || collided.set(true));
+    }
+    let mut slow_frames: u32 = 0; // frames of slow motion left
+
+    let mut recorder = options.record.as_ref().map(|_| capture::Recorder::new());
+
+    let monitor_commands = monitor::spawn_reader();
+
+    let mut throughput = Throughput::new();
+    let mut show_stats = false;
+    let mut waiting_for_key = false; // shown so a ROM parked on FX0A doesn't look frozen
+    let mut show_grid = false;
+    let mut show_opcode = false; // the last instruction in the corner, refreshed at 60Hz
+    let mut show_sound_bar = false; // the sound timer as a bar, to see beeps even when muted
+    let mut menu = Menu::new();
+    let mut settings = Settings {
+        instructions_per_second: options.instructions_per_second,
+        palette: options.palette,
+    };
+
+    let started = Instant::now();
+    let limit_reached = |emulator: &Emulator| {
+        options
+            .limit_cycles
+            .is_some_and(|limit| emulator.cycles() >= limit)
+            || options
+                .timeout
+                .is_some_and(|timeout| started.elapsed() >= timeout)
+    };
+
+    'running: loop {
+        if limit_reached(&emulator) {
+            break;
+        }
+
+        let mut ticks = match cycle_clock.as_mut() {
+            Some(clock) => clock.due_ticks(emulator.cycles()),
+            None => timer_clock.due_ticks(),
+        }
+        .min(u8::MAX as u32) as u8;
+        if (paused || menu.open) && cycle_clock.is_none() {
+            // the machine is frozen, so the time spent paused never reaches the timers
+            ticks = 0;
+        }
+        for _ in 0..ticks {
+            input.handle(&mut emulator, InputEvent::Tick);
+        }
+
+        slow_frames = slow_frames.saturating_sub(ticks as u32);
+        if collided.as_ref().is_some_and(|collided| collided.take()) {
+            slow_frames = SLOW_MOTION_FRAMES;
+        }
+        let interval = if slow_frames > 0 {
+            instruction_interval * SLOW_MOTION_FACTOR
+        } else {
+            instruction_interval
+        };
+
+        if ticks > 0 {
+            if show_opcode || show_sound_bar {
+                emulator.display.draw = true;
+            }
+            if let Some(audio) = audio.as_mut() {
+                audio.update(&emulator);
+            }
+
+            if let Some(ghosting) = ghosting.as_mut() {
+                if ghosting.update(&emulator.display) {
+                    emulator.display.draw = true;
+                }
+            }
+
+            // sampling on timer ticks records at 60fps regardless of how often the ROM draws
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.capture(&emulator.display);
+            }
+        }
+
+        for event in event_pump.poll_iter() {
+            if let Event::Window {
+                win_event: WindowEvent::SizeChanged(new_width, new_height),
+                ..
+            } = event
+            {
+                (width, height) = (new_width.max(1) as u32, new_height.max(1) as u32);
+                // the dirty target matches the window, so it starts over at the new size
+                if let Some(target) = dirty_target.as_mut() {
+                    *target = texture_creator
+                        .create_texture_target(None, width, height)
+                        .unwrap();
+                    drawn_with = None;
+                }
+                emulator.display.draw = true;
+                continue;
+            }
+            if menu.open {
+                match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown {
+                        keycode: Some(key), ..
+                    } => {
+                        match menu.handle_key(key, &mut settings, &mut emulator.quirks) {
+                            Action::None => {}
+                            Action::SpeedChanged => {
+                                instruction_interval =
+                                    interval_at(settings.instructions_per_second);
+                                if let Some(clock) = cycle_clock.as_mut() {
+                                    clock.set_speed(settings.instructions_per_second);
+                                }
+                            }
+                            Action::Close => {
+                                menu.open = false;
+                                last_instruction_time = Instant::now();
+                            }
+                            Action::Quit => break 'running,
+                        }
+                        emulator.display.draw = true;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    repeat: false,
+                    ..
+                } => {
+                    menu.open = true;
+                    emulator.display.draw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(STATS_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    show_stats = !show_stats;
+                    emulator.display.draw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(GRID_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    show_grid = !show_grid;
+                    emulator.display.draw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(OPCODE_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    show_opcode = !show_opcode;
+                    emulator.display.draw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(SOUND_BAR_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    show_sound_bar = !show_sound_bar;
+                    emulator.display.draw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(MUTE_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(audio) = audio.as_mut() {
+                        let muted = audio.toggle_mute();
+                        println!("{}", if muted { "Muted" } else { "Unmuted" });
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(SCREENSHOT_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    let path = capture::screenshot_path();
+                    match capture::save_png(
+                        &path,
+                        &emulator.display,
+                        &settings.palette,
+                        layout(&emulator.display, (width, height)).0,
+                    ) {
+                        Ok(()) => println!("Saved screenshot to {}", path.display()),
+                        Err(err) => eprintln!("Could not save screenshot: {}", err),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(PAUSE_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    paused = !paused;
+                    hexdump_due = paused;
+                    println!(
+                        "{}",
+                        if paused {
+                            "Paused, F10 steps, F11 steps over calls and monitor commands can be typed here"
+                        } else {
+                            "Resumed"
+                        }
+                    );
+                }
+                Event::KeyDown {
+                    keycode: Some(STEP_KEY),
+                    ..
+                } => step = paused,
+                // runs a call through to its return, pausing on the break that reports it
+                Event::KeyDown {
+                    keycode: Some(STEP_OVER_KEY),
+                    ..
+                } if paused => {
+                    if emulator.step_over() {
+                        paused = false;
+                    } else {
+                        step = true;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(REWIND_KEY),
+                    ..
+                } => {
+                    // stepping back out of an error is allowed, to see what led up to it
+                    paused = true;
+                    if rewind.step_back(&mut emulator) {
+                        input.rewind(emulator.cycles());
+                        halted = false;
+                        hexdump_due = true;
+                        print!("{}", emulator.dump_state());
+                    } else {
+                        println!("Nothing left to rewind");
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(TURBO_KEY),
+                    ..
+                } => turbo = true,
+                Event::KeyUp {
+                    keycode: Some(TURBO_KEY),
+                    ..
+                } => turbo = false,
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => {
+                    if let Some(hex) = emulator.controller.map_to_hex(key) {
+                        input.handle(&mut emulator, InputEvent::Press(hex));
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(key), ..
+                } => {
+                    if let Some(hex) = emulator.controller.map_to_hex(key) {
+                        input.handle(&mut emulator, InputEvent::Release(hex));
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(subsystem) = &game_controller_subsystem {
+                        match subsystem.open(which) {
+                            Ok(gamepad) => {
+                                println!("Connected {}", gamepad.name());
+                                gamepads.push(gamepad);
+                            }
+                            Err(err) => eprintln!("Could not open gamepad: {}", err),
+                        }
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    gamepads.retain(|gamepad| {
+                        let removed = gamepad.instance_id() == which;
+                        if removed {
+                            println!("Disconnected {}", gamepad.name());
+                        }
+                        !removed
+                    });
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    if let Some(hex) = emulator.controller.map_button(button) {
+                        input.handle(&mut emulator, InputEvent::Press(hex));
+                    }
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    if let Some(hex) = emulator.controller.map_button(button) {
+                        input.handle(&mut emulator, InputEvent::Release(hex));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(watcher) = file_watcher
+            .as_mut()
+            .and_then(|watcher| watcher.changed().then_some(watcher))
+        {
+            // a half-written file fails to read or load, and the next change tries again
+            match fs::read(&watcher.path)
+                .map_err(|err| err.to_string())
+                .and_then(|program| emulator.reset(program).map_err(|err| err.to_string()))
+            {
+                Ok(()) => {
+                    println!("Reloaded {}", watcher.path.display());
+                    halted = false;
+                    rewind = Rewind::new(options.rewind_depth);
+                }
+                Err(err) => eprintln!("Could not reload {}: {}", watcher.path.display(), err),
+            }
+        }
+
+        for command in monitor_commands.try_iter() {
+            if !paused {
+                println!("Pause with F5 before entering monitor commands");
+                continue;
+            }
+            match monitor::execute(&mut emulator, &command) {
+                Ok(output) => println!("{}", output),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+
+        if halted || menu.open || (paused && !step) {
+            std::thread::sleep(Duration::from_millis(16));
+            last_instruction_time = Instant::now();
+        } else if step {
+            match run_cycle(&mut emulator, &mut input, &mut rewind) {
+                Ok(()) => throughput.instruction(),
+                Err(err) => {
+                    eprintln!("Emulation halted: {}", err);
+                    halted = true;
+                }
+            }
+            step = false;
+            hexdump_due = true;
+        } else if let Some(clock) = cycle_clock.as_ref() {
+            // a frame's worth of instructions, then back round to tick the timers and render
+            while emulator.cycles() < clock.next_tick() {
+                if let Err(err) = run_cycle(&mut emulator, &mut input, &mut rewind) {
+                    eprintln!("Emulation halted: {}", err);
+                    halted = true;
+                    break;
+                }
+                throughput.instruction();
+                if emulator.has_break() || limit_reached(&emulator) {
+                    break;
+                }
+            }
+        } else if turbo {
+            // timers keep ticking at 60Hz from the wall clock, so delay loops still finish
+            let slice_end = Instant::now() + TURBO_SLICE;
+            while Instant::now() < slice_end {
+                if let Err(err) = run_cycle(&mut emulator, &mut input, &mut rewind) {
+                    eprintln!("Emulation halted: {}", err);
+                    halted = true;
+                    break;
+                }
+                throughput.instruction();
+                if emulator.has_break() || limit_reached(&emulator) {
+                    break;
+                }
+            }
+            last_instruction_time = Instant::now();
+        } else if let Some(per_frame) = options.instructions_per_frame {
+            // the budget for every frame whose timer tick came due this pass
+            for _ in 0..ticks as u32 * per_frame {
+                if let Err(err) = run_cycle(&mut emulator, &mut input, &mut rewind) {
+                    eprintln!("Emulation halted: {}", err);
+                    halted = true;
+                    break;
+                }
+                throughput.instruction();
+                if emulator.has_break() || limit_reached(&emulator) {
+                    break;
+                }
+            }
+            if ticks == 0 {
+                // nothing due until the next tick, so don't spin
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        } else if options.vsync {
+            // present blocks until the next refresh, so catch up on every instruction due since the last frame
+            let due = last_instruction_time.elapsed().as_nanos() / interval.as_nanos();
+            for _ in 0..due {
+                if let Err(err) = run_cycle(&mut emulator, &mut input, &mut rewind) {
+                    eprintln!("Emulation halted: {}", err);
+                    halted = true;
+                    break;
+                }
+                throughput.instruction();
+                if emulator.has_break() || limit_reached(&emulator) {
+                    break;
+                }
+            }
+            last_instruction_time += interval * due as u32;
+        } else if last_instruction_time.elapsed() >= interval {
+            // Check if it's time to execute the next instruction
+            match run_cycle(&mut emulator, &mut input, &mut rewind) {
+                Ok(()) => throughput.instruction(),
+                Err(err) => {
+                    eprintln!("Emulation halted: {}", err);
+                    halted = true;
+                }
+            }
+
+            last_instruction_time = Instant::now();
+        }
+
+        if let Some(reason) = emulator.take_break() {
+            println!("{}", reason);
+            print!("{}", emulator.dump_state());
+            paused = true;
+            hexdump_due = true;
+        }
+
+        if hexdump_due && options.hexdump {
+            print!(
+                "PC\n{}I\n{}",
+                monitor::hexdump(&emulator, emulator.program_counter()),
+                monitor::hexdump(&emulator, emulator.index_register())
+            );
+        }
+        hexdump_due = false;
+
+        let stats_updated = throughput.update() && show_stats;
+        let waiting_changed = emulator.waiting_for_key() != waiting_for_key;
+        waiting_for_key = emulator.waiting_for_key();
+
+        // Rerender if necessary. with vsync every refresh is presented
+        if options.vsync || emulator.display.draw || stats_updated || waiting_changed {
+            let drawn = match (texture.as_mut(), dirty_target.as_mut()) {
+                (Some(texture), _) => render_texture(
+                    &mut canvas,
+                    texture,
+                    &emulator.display,
+                    &settings.palette,
+                    ghosting.as_ref(),
+                    (width, height),
+                ),
+                (None, Some(target)) => render_dirty(
+                    &mut canvas,
+                    target,
+                    &mut drawn_with,
+                    &mut emulator.display,
+                    &settings.palette,
+                    (width, height),
+                ),
+                (None, None) => render(
+                    &mut canvas,
+                    &emulator.display,
+                    &settings.palette,
+                    ghosting.as_ref(),
+                    (width, height),
+                ),
+            };
+            if show_grid {
+                draw_grid(&mut canvas, &emulator.display, (width, height));
+            }
+            if show_stats {
+                throughput.draw(&mut canvas, OVERLAY_PIXEL_SIZE);
+            }
+            if show_sound_bar {
+                draw_sound_bar(&mut canvas, emulator.sound_timer(), width);
+            }
+            if let Some(instruction) = emulator.last_instruction().filter(|_| show_opcode) {
+                draw_opcode(&mut canvas, &instruction, (width, height));
+            }
+            if menu.open {
+                menu.draw(&mut canvas, &settings, &emulator.quirks, OVERLAY_PIXEL_SIZE);
+            } else if waiting_for_key {
+                let size = OVERLAY_PIXEL_SIZE as i32;
+                draw_text(
+                    &mut canvas,
+                    "WAITING FOR KEY",
+                    size * 2,
+                    height as i32 - size * 7,
+                    OVERLAY_PIXEL_SIZE,
+                );
+            }
+
+            // Update the canvas
+            canvas.present();
+            throughput.frame(drawn);
+            emulator.display.draw = false;
+        }
+    }
+
+    if let (Some(recorder), Some(path)) = (recorder, &options.record) {
+        match recorder.save(path, &settings.palette) {
+            Ok(()) => println!("Saved recording to {}", path.display()),
+            Err(err) => eprintln!("Could not save recording: {}", err),
+        }
+    }
+
+    input.finish();
+
+    emulator
+}